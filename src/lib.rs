@@ -1,18 +1,83 @@
-use std::alloc::{Layout, alloc, dealloc};
-use std::ffi::{c_int, c_void};
-use std::fmt::{self, Display};
+use std::alloc::{Layout, handle_alloc_error};
+#[cfg(not(all(feature = "mremap", target_os = "linux")))]
+use std::alloc::{alloc, dealloc};
+#[cfg(feature = "event-log")]
+use std::collections::VecDeque;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CStr, CString, c_char, c_int, c_void};
+use std::fmt::{self, Display, Write as _};
+use std::hash::{DefaultHasher, Hash as _, Hasher as _};
 use std::mem::{self, offset_of};
+use std::panic;
 use std::ptr;
-use std::sync::{LazyLock, Mutex};
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex, MutexGuard, Once, TryLockError};
+use std::time::Instant;
 
 use nounwind::nounwind;
+use strum::EnumCount as _;
 
 unsafe extern "C" {
-    static FIELD_COUNT_MASK: c_int;
-    static TAG_MASK: c_int;
+    #[cfg(not(feature = "wide-header"))]
+    static FIELD_COUNT_MASK: Header;
+    #[cfg(not(feature = "wide-header"))]
+    static TAG_MASK: Header;
+
+    #[cfg(feature = "wide-header")]
+    #[link_name = "FIELD_COUNT_MASK64"]
+    static FIELD_COUNT_MASK: Header;
+    #[cfg(feature = "wide-header")]
+    #[link_name = "TAG_MASK64"]
+    static TAG_MASK: Header;
+
     static max_alloc_size: u64;
+
+    #[cfg(feature = "checked-writes")]
+    #[link_name = "the_ZERO"]
+    static THE_ZERO: StellaObj;
+    #[cfg(feature = "checked-writes")]
+    #[link_name = "the_UNIT"]
+    static THE_UNIT: StellaObj;
+    #[cfg(feature = "checked-writes")]
+    #[link_name = "the_EMPTY"]
+    static THE_EMPTY: StellaObj;
+    #[cfg(feature = "checked-writes")]
+    #[link_name = "the_EMPTY_TUPLE"]
+    static THE_EMPTY_TUPLE: StellaObj;
+    #[cfg(feature = "checked-writes")]
+    #[link_name = "the_FALSE"]
+    static THE_FALSE: StellaObj;
+    #[cfg(feature = "checked-writes")]
+    #[link_name = "the_TRUE"]
+    static THE_TRUE: StellaObj;
+}
+
+/// Returns `true` if `ptr` points to one of the objects the runtime allocates statically.
+#[cfg(feature = "checked-writes")]
+fn is_static_obj(ptr: ObjPtr) -> bool {
+    let statics: [*const StellaObj; 6] = [
+        &raw const THE_ZERO,
+        &raw const THE_UNIT,
+        &raw const THE_EMPTY,
+        &raw const THE_EMPTY_TUPLE,
+        &raw const THE_FALSE,
+        &raw const THE_TRUE,
+    ];
+
+    statics.contains(&ptr.0.cast_const())
 }
 
+/// The type of an object header, which packs the tag into the low 4 bits and the field count into
+/// the bits above (as selected by `TAG_MASK` and `FIELD_COUNT_MASK`).
+#[cfg(not(feature = "wide-header"))]
+type Header = c_int;
+
+/// The type of an object header, which packs the tag into the low 4 bits and the field count into
+/// the bits above (as selected by `TAG_MASK64` and `FIELD_COUNT_MASK64`).
+#[cfg(feature = "wide-header")]
+type Header = i64;
+
 const FIELD_SIZE: usize = mem::size_of::<*const c_void>();
 
 /// The alignment of allocated objects.
@@ -23,16 +88,28 @@ const ALIGNMENT: usize = const {
     if obj_align > 8 { obj_align } else { 8 }
 };
 
+/// The alignment of the semi-spaces' backing memory.
+///
+/// This is the strictest alignment [`Gc::alloc_aligned`] can provide.
+const SPACE_ALIGNMENT: usize = 64;
+
+/// The smallest semi-space size [`Gc::new`] accepts.
+const MIN_SPACE_SIZE: usize = SPACE_ALIGNMENT;
+
+/// The byte the from-space is overwritten with once a GC cycle completes.
+#[cfg(feature = "poison")]
+const POISON_BYTE: u8 = 0xdd;
+
 /// A FFI-compatible definition of `stella_object`.
 #[repr(C)]
 struct StellaObj {
-    header: c_int,
+    header: Header,
     fields: [ObjPtr; 0],
 }
 
 /// A FFI-compatible definition of `enum TAG`.
 #[repr(C)]
-#[derive(strum::FromRepr, strum::Display, Debug, Clone, Copy)]
+#[derive(strum::FromRepr, strum::Display, strum::EnumCount, Debug, Clone, Copy)]
 #[strum(serialize_all = "kebab-case")]
 enum StellaTag {
     Zero,
@@ -50,16 +127,19 @@ enum StellaTag {
 }
 
 /// An enumeration of possible kinds of stella object fields.
+///
+/// The discriminants are the kinds reported by `gc_walk_object_fields`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
 enum StellaFieldKind {
     /// The field holds a pointer to another stella object.
-    Obj,
+    Obj = 0,
 
     /// The field holds an arbitrary pointer.
-    Raw,
+    Raw = 1,
 
     /// The field is not supposed to be there at all.
-    Invalid,
+    Invalid = 2,
 }
 
 impl StellaTag {
@@ -96,11 +176,25 @@ impl StellaTag {
             StellaTag::Cons => StellaFieldKind::Invalid,
         }
     }
+
+    /// Returns the number of fields every stella object with this tag has, or `None` if it varies.
+    fn arity(self) -> Option<usize> {
+        match self {
+            StellaTag::Zero
+            | StellaTag::False
+            | StellaTag::True
+            | StellaTag::Unit
+            | StellaTag::Empty => Some(0),
+            StellaTag::Succ | StellaTag::Ref | StellaTag::Inl | StellaTag::Inr => Some(1),
+            StellaTag::Cons => Some(2),
+            StellaTag::Fn | StellaTag::Tuple => None,
+        }
+    }
 }
 
 /// A wrapper around a pointer to a stella object.
 #[repr(transparent)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ObjPtr(*mut StellaObj);
 
 impl ObjPtr {
@@ -119,20 +213,53 @@ impl ObjPtr {
     /// # Safety
     /// The underlying pointer must point to a valid object.
     unsafe fn tag(self) -> StellaTag {
+        unsafe { self.try_tag() }.unwrap()
+    }
+
+    /// Returns the tag of the object, or `None` if the header holds an unknown tag.
+    ///
+    /// # Safety
+    /// The underlying pointer must point to a readable object header.
+    unsafe fn try_tag(self) -> Option<StellaTag> {
+        StellaTag::from_repr(unsafe { self.raw_tag() })
+    }
+
+    /// Returns the tag bits of the object's header without interpreting them.
+    ///
+    /// # Safety
+    /// The underlying pointer must point to a readable object header.
+    unsafe fn raw_tag(self) -> usize {
         let header = unsafe { (*self.0).header } as usize;
-        let tag = header & unsafe { TAG_MASK as usize };
 
-        StellaTag::from_repr(tag).unwrap()
+        header & unsafe { TAG_MASK as usize }
     }
 
     /// Returns the size of the object (counting both the header and the fields).
     ///
+    /// An object without fields still occupies [`MIN_OBJ_SIZE`] bytes.
+    ///
+    /// # Panics
+    /// Panics if the size overflows `usize`, which indicates a corrupt header.
+    ///
     /// # Safety
     /// The underlying pointer must point to a valid object.
     unsafe fn size(self) -> usize {
         let field_count = unsafe { self.field_count() };
 
-        offset_of!(StellaObj, fields) + field_count * FIELD_SIZE
+        field_offset(field_count.max(1)).unwrap_or_else(|| {
+            panic!(
+                "corrupt header at {:?}: the size of {field_count} fields overflows",
+                self.0,
+            )
+        })
+    }
+
+    /// Returns `true` if the object is a padding filler rather than a stella object.
+    ///
+    /// # Safety
+    /// The underlying pointer must point to a valid object or a padding filler.
+    unsafe fn is_padding(self) -> bool {
+        unsafe { (*self.0).header == TAG_MASK }
     }
 
     /// Computes a pointer to a field with the given index.
@@ -141,28 +268,118 @@ impl ObjPtr {
     /// The underlying pointer must point to a valid object, and the `idx` must not exceed the field
     /// count.
     unsafe fn field(self, idx: usize) -> *mut ObjPtr {
-        unsafe {
-            self.0
-                .byte_add(offset_of!(StellaObj, fields) + idx * FIELD_SIZE)
-                .cast()
-        }
+        let offset = field_offset(idx)
+            .unwrap_or_else(|| panic!("the offset of field #{idx} of {:?} overflows", self.0));
+
+        unsafe { self.0.byte_add(offset).cast() }
     }
 }
 
+/// Computes the offset of a field with the given index from the start of the object.
+///
+/// Returns `None` on overflow.
+fn field_offset(idx: usize) -> Option<usize> {
+    idx.checked_mul(FIELD_SIZE)?
+        .checked_add(offset_of!(StellaObj, fields))
+}
+
+/// Rounds `size` up so it has the given alignment, or returns `None` if the result doesn't fit in
+/// a `usize`.
+///
+/// `align` must be a power of two.
+fn checked_align_up(size: usize, align: usize) -> Option<usize> {
+    debug_assert!(align.is_power_of_two(), "invalid alignment: {align}");
+
+    Some(size.checked_add(align - 1)? & !(align - 1))
+}
+
 /// Rounds `size` up so it has the given alignment.
+///
+/// `align` must be a power of two.
+///
+/// # Panics
+/// Panics if the result doesn't fit in a `usize`.
 fn align_up(size: usize, align: usize) -> usize {
-    let misalignment = size % align;
-
-    size + if misalignment > 0 {
-        align - misalignment
-    } else {
-        0
-    }
+    checked_align_up(size, align)
+        .unwrap_or_else(|| panic!("{size} overflows when aligned to {align}"))
 }
 
 /// Rounds `size` down so it has the given alignment.
+///
+/// `align` must be a power of two.
 fn align_down(size: usize, align: usize) -> usize {
-    size - size % align
+    debug_assert!(align.is_power_of_two(), "invalid alignment: {align}");
+
+    size & !(align - 1)
+}
+
+/// The size of a padding filler.
+///
+/// A filler is a bare header with all tag bits set and no fields. Gaps left by aligning objects are
+/// covered by these so that the space can still be walked object by object.
+const PADDING_SIZE: usize = offset_of!(StellaObj, fields);
+
+/// The smallest amount of memory a GC-managed object occupies.
+///
+/// Objects without fields get room for one anyway: the forwarding pointer of an evacuated object
+/// is stored in its first field.
+const MIN_OBJ_SIZE: usize = offset_of!(StellaObj, fields) + FIELD_SIZE;
+
+/// Rounds a requested allocation size up to the size the object actually occupies, or returns
+/// `usize::MAX` if the result doesn't fit in a `usize`.
+fn obj_size_for(size: usize) -> usize {
+    checked_align_up(size.max(MIN_OBJ_SIZE), ALIGNMENT).unwrap_or(usize::MAX)
+}
+
+/// Covers the range `start..end` with padding fillers.
+///
+/// # Safety
+/// The range must be writable, aligned to [`PADDING_SIZE`], and its length must be a multiple of
+/// [`PADDING_SIZE`].
+unsafe fn fill_padding(start: *mut u8, end: *mut u8) {
+    let mut addr = start;
+
+    while addr < end {
+        unsafe { ptr::write(addr.cast::<Header>(), TAG_MASK) };
+        addr = unsafe { addr.byte_add(PADDING_SIZE) };
+    }
+}
+
+/// Writes to every memory page overlapping the range `start..end` so that the OS backs them with
+/// physical memory right away instead of on first access.
+///
+/// Does nothing on platforms without demand paging.
+///
+/// # Safety
+/// The range must be writable, and its contents are clobbered.
+unsafe fn prefault(start: *mut u8, end: *mut u8) {
+    #[cfg(unix)]
+    {
+        let page_size = usize::try_from(unsafe { libc::sysconf(libc::_SC_PAGESIZE) })
+            .ok()
+            .filter(|&page_size| page_size > 0)
+            .unwrap_or(4096);
+        let mut addr = start;
+
+        while addr < end {
+            unsafe { ptr::write_volatile(addr, 0) };
+            addr = addr.wrapping_byte_add(page_size - addr.addr() % page_size);
+        }
+    }
+
+    #[cfg(not(unix))]
+    let _ = (start, end);
+}
+
+/// The number of buckets in [`Gc::size_histogram`].
+const SIZE_CLASSES: usize = usize::BITS as usize;
+
+/// Returns the index of the size class (a power-of-two bucket) an object of the given size falls
+/// into.
+///
+/// The bucket `i` holds objects of sizes `2^(i - 1) + 1..=2^i`.
+fn size_class(size: usize) -> usize {
+    (size.next_power_of_two().trailing_zeros() as usize).min(SIZE_CLASSES - 1)
 }
 
 /// A contiguous bounded chunk of memory; one of the two semi-spaces managed by the GC.
@@ -172,24 +389,186 @@ fn align_down(size: usize, align: usize) -> usize {
 struct Space {
     start: *mut u8,
     size: usize,
+
+    /// Whether the memory is owned by someone else and must not be deallocated.
+    borrowed: bool,
+
+    /// The size of the address range mapped for the semi-space, which extends past its end to
+    /// leave room for growing in place (see [`Space::grow_in_place`]). Only the first `size` bytes
+    /// are guaranteed to be accessible.
+    #[cfg(all(feature = "mremap", target_os = "linux"))]
+    reserved: usize,
 }
 
 impl Space {
     /// Allocates a new semi-space no larger than `size`.
+    ///
+    /// Aborts the process via [`handle_alloc_error`] if the system allocator fails.
+    ///
+    /// # Panics
+    /// Panics if `size` is too large to be a valid allocation size.
     fn alloc(size: usize) -> Self {
-        let size = align_down(size.max(1), ALIGNMENT);
+        let layout = Self::layout(size).expect("semi-space size is too large");
 
-        if size == 0 {
-            Self {
-                start: ptr::null_mut(),
-                size: 0,
-            }
+        Self::try_alloc(size).unwrap_or_else(|| handle_alloc_error(layout))
+    }
+
+    /// Allocates a new semi-space no larger than `size`.
+    ///
+    /// Returns `None` if the system allocator fails or `size` is too large.
+    fn try_alloc(size: usize) -> Option<Self> {
+        #[cfg(test)]
+        if tests::SPACE_ALLOC_FAILS.get() {
+            return None;
+        }
+
+        let layout = Self::layout(size)?;
+
+        if layout.size() == 0 {
+            Some(Self::default())
         } else {
-            let layout = unsafe { Layout::from_size_align_unchecked(size, ALIGNMENT) };
+            #[cfg(not(all(feature = "mremap", target_os = "linux")))]
             let start = unsafe { alloc(layout) };
 
-            Self { start, size }
+            #[cfg(all(feature = "mremap", target_os = "linux"))]
+            let (start, reserved) = Self::map(layout.size()).unwrap_or((ptr::null_mut(), 0));
+
+            #[cfg(feature = "prefault")]
+            if !start.is_null() {
+                unsafe { prefault(start, start.byte_add(layout.size())) };
+            }
+
+            (!start.is_null()).then_some(Self {
+                start,
+                size: layout.size(),
+                borrowed: false,
+                #[cfg(all(feature = "mremap", target_os = "linux"))]
+                reserved,
+            })
+        }
+    }
+
+    /// Creates a semi-space backed by `size` bytes of memory at `start` owned by the caller.
+    ///
+    /// # Panics
+    /// Panics if `start` isn't aligned to [`SPACE_ALIGNMENT`] or `size` isn't a multiple of
+    /// [`ALIGNMENT`].
+    ///
+    /// # Safety
+    /// The memory must remain valid and otherwise unused for as long as the semi-space lives.
+    #[cfg(feature = "testing")]
+    unsafe fn borrowed(start: *mut u8, size: usize) -> Self {
+        assert!(
+            start.addr().is_multiple_of(SPACE_ALIGNMENT),
+            "the space must be aligned to {SPACE_ALIGNMENT} bytes",
+        );
+        assert!(
+            size.is_multiple_of(ALIGNMENT),
+            "the space size must be a multiple of {ALIGNMENT}",
+        );
+
+        Self {
+            start,
+            size,
+            borrowed: true,
+            #[cfg(all(feature = "mremap", target_os = "linux"))]
+            reserved: size,
+        }
+    }
+
+    /// Maps `size` bytes of readable and writable memory, followed by an inaccessible address
+    /// range the semi-space can later grow into.
+    ///
+    /// Returns the start of the mapping and its total size, or `None` if `mmap` fails. The mapping
+    /// is page-aligned, which is stricter than [`SPACE_ALIGNMENT`].
+    #[cfg(all(feature = "mremap", target_os = "linux"))]
+    fn map(size: usize) -> Option<(*mut u8, usize)> {
+        let map = |len| match unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        } {
+            libc::MAP_FAILED => None,
+            start => Some((start.cast::<u8>(), len)),
+        };
+
+        // the headroom is only address space, but that may still run out on 32-bit targets.
+        let (start, reserved) =
+            map(size.saturating_mul(SPACE_RESERVE_FACTOR)).or_else(|| map(size))?;
+
+        if unsafe { libc::mprotect(start.cast(), size, libc::PROT_READ | libc::PROT_WRITE) } != 0 {
+            unsafe { libc::munmap(start.cast(), reserved) };
+
+            return None;
+        }
+
+        Some((start, reserved))
+    }
+
+    /// Tries to extend the semi-space to `new_size` bytes without moving it.
+    ///
+    /// The semi-space first grows into the address range reserved after it. Past that, the mapping
+    /// is extended with `mremap`, which fails if the memory right after it is taken.
+    ///
+    /// Returns `true` on success. Fails if the semi-space is borrowed or empty, if `new_size`
+    /// isn't larger than the current size, or if there's no room to grow. Only supported with the
+    /// `mremap` feature on Linux; always fails otherwise.
+    fn grow_in_place(&mut self, new_size: usize) -> bool {
+        let Some(layout) = Self::layout(new_size) else {
+            return false;
+        };
+
+        if self.borrowed || self.start.is_null() || layout.size() <= self.size {
+            return false;
+        }
+
+        #[cfg(all(feature = "mremap", target_os = "linux"))]
+        {
+            let rw = libc::PROT_READ | libc::PROT_WRITE;
+
+            if layout.size() > self.reserved {
+                // `mremap` only extends a mapping made of a single region with uniform protection.
+                if unsafe { libc::mprotect(self.start.cast(), self.reserved, rw) } != 0 {
+                    return false;
+                }
+
+                // without `MREMAP_MAYMOVE`, the mapping is either extended where it is or left alone.
+                let result =
+                    unsafe { libc::mremap(self.start.cast(), self.reserved, layout.size(), 0) };
+
+                if result == libc::MAP_FAILED {
+                    return false;
+                }
+
+                self.reserved = layout.size();
+            } else if unsafe { libc::mprotect(self.start.cast(), layout.size(), rw) } != 0 {
+                return false;
+            }
+
+            #[cfg(feature = "prefault")]
+            unsafe {
+                prefault(self.end(), self.start.byte_add(layout.size()))
+            };
+
+            self.size = layout.size();
+
+            true
         }
+
+        #[cfg(not(all(feature = "mremap", target_os = "linux")))]
+        false
+    }
+
+    /// Returns the layout of the memory backing a semi-space no larger than `size`.
+    ///
+    /// Returns `None` if the size is too large.
+    fn layout(size: usize) -> Option<Layout> {
+        Layout::from_size_align(align_down(size.max(1), ALIGNMENT), SPACE_ALIGNMENT).ok()
     }
 
     /// Returns the pointer one past the last byte belonging to this semi-space.
@@ -203,18 +582,152 @@ impl Space {
     fn contains(&self, ptr: *mut u8) -> bool {
         !ptr.is_null() && (self.start..self.end()).contains(&ptr)
     }
+
+    /// Checks if this semi-space shares any bytes with `other`.
+    fn overlaps(&self, other: &Space) -> bool {
+        self.size > 0 && other.size > 0 && self.start < other.end() && other.start < self.end()
+    }
 }
 
 impl Drop for Space {
     fn drop(&mut self) {
-        if !self.start.is_null() {
-            let layout = unsafe { Layout::from_size_align_unchecked(self.size, ALIGNMENT) };
-            unsafe { dealloc(self.start, layout) };
+        if !self.start.is_null() && !self.borrowed {
+            #[cfg(not(all(feature = "mremap", target_os = "linux")))]
+            {
+                let layout =
+                    unsafe { Layout::from_size_align_unchecked(self.size, SPACE_ALIGNMENT) };
+                unsafe { dealloc(self.start, layout) };
+            }
+
+            #[cfg(all(feature = "mremap", target_os = "linux"))]
+            unsafe {
+                libc::munmap(self.start.cast(), self.reserved)
+            };
+
             self.start = ptr::null_mut();
         }
     }
 }
 
+/// The size of the address range reserved for a semi-space relative to its initial size, which
+/// bounds how much it can grow in place without relying on the memory after the range being free.
+#[cfg(all(feature = "mremap", target_os = "linux"))]
+const SPACE_RESERVE_FACTOR: usize = 8;
+
+/// The maximum number of bytes scanned per allocation while GC is in progress, unless the to-space
+/// is running out of free memory (see [`Gc::pace_gc`]).
+const MAX_SCAN_SLICE: usize = 16 * 1024;
+
+/// The number of collector steps a GC cycle may take on top of the ones its heap size accounts for
+/// before [`Gc::tick_watchdog`] declares it stuck.
+const CYCLE_STEP_SLACK: usize = 1024;
+
+/// The default maximum number of roots forwarded per increment of a GC cycle (see
+/// [`Gc::root_slice`]).
+const DEFAULT_ROOT_SLICE: usize = 4096;
+
+/// The weight of the latest cycle in the exponential moving average of the survival rate (see
+/// [`Gc::survival_ratio`]).
+const SURVIVAL_EMA_WEIGHT: f64 = 0.5;
+
+/// The bounds for the number of bytes scanned per allocated byte (see [`Stats::scan_ratio`]).
+const SCAN_RATIO_RANGE: (f64, f64) = (0.125, 8.0);
+
+/// The size of the buffer [`Gc::raw_field_formatter`] writes to.
+const RAW_FIELD_BUF_SIZE: usize = 64;
+
+/// The minimum size of a chunk backing the immortal space.
+const IMMORTAL_CHUNK_SIZE: usize = 64 * 1024;
+
+/// A region for objects that are never moved or reclaimed.
+///
+/// The region grows in chunks as needed. Objects are bump-allocated in the last chunk.
+#[derive(Default, Debug)]
+struct ImmortalSpace {
+    /// The chunks backing the region, each paired with the number of bytes used in it.
+    chunks: Vec<(Space, usize)>,
+}
+
+impl ImmortalSpace {
+    /// Allocates `size` bytes in the region, adding a new chunk if the last one can't fit them.
+    ///
+    /// `size` must be a multiple of [`ALIGNMENT`].
+    fn alloc(&mut self, size: usize) -> *mut u8 {
+        self.alloc_aligned(size, ALIGNMENT)
+    }
+
+    /// Allocates `size` bytes whose address is a multiple of `align` in the region, adding a new
+    /// chunk if the last one can't fit them. The gap left in front of them is filled with padding.
+    ///
+    /// `size` must be a multiple of [`ALIGNMENT`], and `align` must be a power of two between
+    /// [`ALIGNMENT`] and [`SPACE_ALIGNMENT`].
+    fn alloc_aligned(&mut self, size: usize, align: usize) -> *mut u8 {
+        let padding = |chunk: &Space, used: usize| {
+            let addr = chunk.start.addr() + used;
+
+            align_up(addr, align) - addr
+        };
+
+        if self
+            .chunks
+            .last()
+            .is_none_or(|(chunk, used)| chunk.size - used < size + padding(chunk, *used))
+        {
+            self.chunks
+                .push((Space::alloc(size.max(IMMORTAL_CHUNK_SIZE)), 0));
+        }
+
+        let (chunk, used) = self.chunks.last_mut().unwrap();
+        let start = unsafe { chunk.start.byte_add(*used) };
+        let result = unsafe { start.byte_add(padding(chunk, *used)) };
+        unsafe { fill_padding(start, result) };
+        *used = unsafe { result.byte_offset_from_unsigned(chunk.start) } + size;
+
+        result
+    }
+
+    /// Checks if a pointer points to an allocated part of the region.
+    fn contains(&self, ptr: *mut u8) -> bool {
+        self.chunks.iter().any(|(chunk, used)| {
+            chunk.contains(ptr) && ptr < unsafe { chunk.start.byte_add(*used) }
+        })
+    }
+
+    /// Returns the end of the allocated part of the chunk `ptr` points to, or `None` if it doesn't
+    /// point to an allocated part of the region.
+    fn allocated_end(&self, ptr: *mut u8) -> Option<*mut u8> {
+        self.chunks.iter().find_map(|(chunk, used)| {
+            let end = unsafe { chunk.start.byte_add(*used) };
+
+            (chunk.contains(ptr) && ptr < end).then_some(end)
+        })
+    }
+
+    /// Returns the number of bytes allocated in the region.
+    fn used(&self) -> usize {
+        self.chunks.iter().map(|&(_, used)| used).sum()
+    }
+
+    /// Calls `f` for every object in the region in allocation order.
+    fn for_each_object(&self, mut f: impl FnMut(ObjPtr)) {
+        for (chunk, used) in &self.chunks {
+            let end = unsafe { chunk.start.byte_add(*used) };
+            let mut addr = chunk.start;
+
+            while addr < end {
+                let ptr = ObjPtr(addr.cast());
+
+                // chunks holding pinned objects have the gaps between them filled with padding.
+                if !unsafe { ptr.is_padding() } {
+                    f(ptr);
+                }
+
+                addr = unsafe { addr.byte_add(ptr.size()) };
+            }
+        }
+    }
+}
+
 /// An enumeration of memory regions addresses may belong to.
 #[derive(strum::Display, Debug, Clone, Copy, PartialEq, Eq)]
 enum SpaceClass {
@@ -232,646 +745,7710 @@ enum SpaceClass {
         offset: usize,
     },
 
+    /// The immortal space.
+    #[strum(to_string = "immortal")]
+    Immortal,
+
     /// Memory not managed by the GC.
     #[strum(to_string = "unmanaged")]
     Unmanaged,
 }
 
-/// Garbage collection statistics.
-#[derive(Default, Debug, Clone, Copy)]
-struct Stats {
-    /// The number of field reads.
-    reads: usize,
-
-    /// The number of field writes.
-    writes: usize,
-
-    /// The number of field reads that triggered a read barrier.
-    read_barriers: usize,
-
-    /// The amount of memory allocated since the start of the program.
-    all_time_allocated: usize,
+/// A reason for an object to fail the checks performed by [`Gc::validate_object`].
+///
+/// The discriminants are the error codes returned by `gc_validate_object`.
+#[derive(strum::Display, Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+enum ObjDefect {
+    /// The pointer is null, misaligned, or doesn't point to an allocated part of a managed space.
+    #[strum(to_string = "not an allocated object in a managed space")]
+    Unmanaged = 1,
+
+    /// The header holds an unknown tag (or the object is a padding filler).
+    #[strum(to_string = "unknown tag")]
+    UnknownTag = 2,
+
+    /// The field count doesn't match the tag's fixed arity.
+    #[strum(to_string = "field count doesn't match the tag")]
+    ArityMismatch = 3,
+
+    /// The object's fields extend past the allocated part of its space.
+    #[strum(to_string = "fields extend past the allocated area")]
+    OutOfBounds = 4,
+
+    /// An `Obj` field points to a free or misaligned location in a managed space.
+    #[strum(to_string = "a field points to free or misaligned memory")]
+    BadField = 5,
+}
 
-    /// The number of allocated objects (i. e., calls to [`Gc::alloc`]) since the start of the
-    /// program.
-    all_time_allocated_objs: usize,
+/// A strategy for keeping the mutator's view of the heap consistent during a GC cycle.
+#[derive(strum::FromRepr, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+enum BarrierMode {
+    /// Baker-style forwarding.
+    ///
+    /// The read barrier forwards every from-space pointer it reads, so the mutator never gets hold
+    /// of one. The forwarding pointer of an evacuated object overwrites its first field.
+    #[default]
+    Baker,
 
-    /// The maximum amount of used memory managed by the GC.
-    max_used: usize,
+    /// Brooks-style forwarding.
+    ///
+    /// Every object in the semi-spaces is followed by a forwarding word pointing either to the
+    /// object itself or to its copy, and the barriers access the object through it. The mutator
+    /// may keep using from-space pointers until the end of the cycle, when they're all forwarded.
+    Brooks,
 
-    /// The number of times garbage collection took place.
+    /// Baker-style forwarding with a snapshot-at-the-beginning (Yuasa) write barrier.
     ///
-    /// Includes the partical GC cycle when garbage collection is in progress.
-    gc_cycles: usize,
+    /// Objects are laid out and read the same way as in the Baker mode, but a write during a GC
+    /// cycle first evacuates the from-space object the field held, so everything reachable when the
+    /// cycle started survives it, even if it's detached from the graph in the meantime.
+    Snapshot,
 }
 
-/// A copying semi-space garbage collector.
-struct Gc {
-    /// The from-space.
+/// An order in which the collector evacuates objects, which determines their layout in the
+/// to-space.
+#[derive(strum::FromRepr, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(C)]
+enum ScanOrder {
+    /// Semi-depth-first order.
     ///
-    /// If garbage collection is not currently underway, this field contains `None`.
-    from_space: Option<Space>,
+    /// Evacuating an object also evacuates a chain of its descendants right after it (see
+    /// [`Gc::chase`]), so linked structures end up laid out contiguously.
+    #[default]
+    DepthFirst,
 
-    /// The to-space.
-    to_space: Space,
+    /// Breadth-first (Cheney) order.
+    ///
+    /// Evacuating an object only copies the object itself, and its fields are left to the scan,
+    /// so siblings end up next to each other.
+    BreadthFirst,
+}
 
-    /// The root stack.
-    roots: Vec<*mut ObjPtr>,
+/// A rule deciding when an allocation starts a GC cycle.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum CollectionPolicy {
+    /// Start a cycle once the to-space can't fit the allocation.
+    #[default]
+    WhenFull,
 
-    /// Whether a garbage collection cycle is currently underway.
-    gc_in_progress: bool,
+    /// Also start a cycle once the given number of bytes has been allocated since the last one
+    /// started.
+    EveryNBytes(usize),
 
-    /// The end of the scanned area in the to-space.
-    scan: *mut u8,
+    /// Also start a cycle once the given number of objects has been allocated since the last one
+    /// started.
+    EveryNAllocations(usize),
 
-    /// If GC is undergoing, the end of the area to scan.
-    /// Otherwise, the place to allocate the next object at.
-    next: *mut u8,
+    /// Never start a cycle on allocation. Once the to-space is full, allocation fails until the
+    /// host collects garbage explicitly.
+    Manual,
+}
 
-    /// If GC is undergoing, the place before which the next object is allocated.
-    /// Otherwise, the end of the free area.
-    limit: *mut u8,
+/// The maximum number of events kept in [`Gc::event_log`]; older ones are discarded.
+#[cfg(feature = "event-log")]
+const EVENT_LOG_CAPACITY: usize = 4096;
+
+/// A GC-relevant event recorded in [`Gc::event_log`].
+#[derive(strum::Display, Debug, Clone, Copy)]
+enum GcEvent {
+    /// An object was allocated.
+    #[strum(to_string = "alloc {size} B at {at}")]
+    Alloc {
+        /// The number of bytes the object occupies.
+        size: usize,
+
+        /// Where the object was placed.
+        at: SpaceClass,
+    },
 
-    /// Garbage collection statistics.
-    stats: Stats,
-}
+    /// A GC cycle started.
+    #[strum(to_string = "cycle #{cycle} started")]
+    CycleStart {
+        /// The ordinal number of the cycle.
+        cycle: usize,
+    },
 
-unsafe impl Send for Gc {}
+    /// A GC cycle finished.
+    #[strum(to_string = "cycle #{cycle} finished: {survived} B survived")]
+    CycleEnd {
+        /// The ordinal number of the cycle.
+        cycle: usize,
 
-impl Gc {
-    /// Creates a new garbage collector instance.
-    ///
-    /// # Safety
-    /// The external variables must have already been initialized to valid values.
-    pub unsafe fn new() -> Self {
-        let to_space = Space::alloc(usize::try_from(unsafe { max_alloc_size }).unwrap());
-        let next = to_space.start;
-        let limit = to_space.end();
+        /// The number of bytes used in the to-space after the cycle.
+        survived: usize,
+    },
 
-        Self {
-            from_space: None,
-            to_space,
+    /// An object was evacuated to the to-space.
+    #[strum(to_string = "forward {from} -> {to}")]
+    Forward {
+        /// The old location of the object.
+        from: SpaceClass,
 
-            roots: Default::default(),
+        /// The new location of the object.
+        to: SpaceClass,
+    },
 
-            gc_in_progress: false,
-            scan: Default::default(),
-            next,
-            limit,
+    /// A field read triggered a read barrier.
+    #[strum(to_string = "read barrier on field #{field_idx} of {obj}")]
+    ReadBarrier {
+        /// The object being read.
+        obj: SpaceClass,
 
-            stats: Default::default(),
-        }
-    }
+        /// The index of the field being read.
+        field_idx: usize,
+    },
+}
 
-    /// Allocates a new object of the given size at `self.next`.
-    ///
+/// A pair of fields of an object traced like the key and the value of an ephemeron (see
+/// [`Gc::conditional_fields`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ConditionalField {
+    /// The index of the field that is only traced if the key is reachable.
+    value_idx: usize,
+
+    /// The index of the field the tracing is gated on. It's never traced itself.
+    key_idx: usize,
+}
+
+impl ConditionalField {
+    /// Returns `true` if the field with the given index is covered by this rule.
+    fn covers(self, idx: usize) -> bool {
+        idx == self.value_idx || idx == self.key_idx
+    }
+}
+
+/// A callback to run once an object dies (see [`Gc::finalizers`]).
+#[derive(Debug, Clone, Copy)]
+struct Finalizer {
+    /// The function to call.
+    cb: extern "C" fn(ctx: *mut c_void),
+
+    /// The argument to pass to `cb`.
+    ctx: *mut c_void,
+}
+
+/// A callback to notify once a GC cycle completes (see [`Gc::post_gc_callback`]).
+type PostGcCallback = extern "C" fn(used: usize, total: usize, survived: usize, cycle: usize);
+
+/// Per-object metadata that doesn't fit into the object header.
+#[derive(Debug, Clone, Copy)]
+struct ObjInfo {
+    /// The alignment of the object.
+    align: usize,
+
+    /// The allocation epoch the object was allocated in (see [`Gc::epoch`]).
+    epoch: u64,
+}
+
+impl Default for ObjInfo {
+    fn default() -> Self {
+        Self {
+            align: ALIGNMENT,
+            epoch: 0,
+        }
+    }
+}
+
+/// The snapshot recorded by [`Gc::collect_into_snapshot`] as the objects get evacuated.
+#[derive(Default, Debug)]
+struct SnapshotRecorder {
+    /// The objects recorded so far, in the format of [`Gc::export_graph`].
+    buf: Vec<u8>,
+
+    /// The offsets of the recorded objects in `buf`, keyed by their to-space copies.
+    offsets: HashMap<ObjPtr, usize>,
+
+    /// The positions in `buf` of the fields referring to the from-space objects that haven't been
+    /// recorded yet, keyed by those objects.
+    pending: HashMap<ObjPtr, Vec<usize>>,
+
+    /// The positions in `buf` of the fields referring to objects outside the semi-spaces, along
+    /// with their values.
+    outside: Vec<(usize, ObjPtr)>,
+}
+
+impl SnapshotRecorder {
+    /// Overwrites the field at the position `at` in the buffer with `word`.
+    fn set_field(&mut self, at: usize, word: usize) {
+        self.buf[at..at + FIELD_SIZE].copy_from_slice(&word.to_ne_bytes());
+    }
+
+    /// Copies an object to the end of the buffer and returns its offset.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn push(&mut self, ptr: ObjPtr) -> usize {
+        let offset = self.buf.len();
+        let size = field_offset(unsafe { ptr.field_count() }).unwrap();
+        self.buf
+            .extend_from_slice(unsafe { slice::from_raw_parts(ptr.0.cast::<u8>(), size) });
+
+        offset
+    }
+}
+
+/// Garbage collection statistics.
+#[derive(Default, Debug, Clone, Copy)]
+struct Stats {
+    /// The number of field writes.
+    writes: usize,
+
+    /// The number of field reads that triggered a read barrier.
+    read_barriers: usize,
+
+    /// The number of field writes that evacuated the overwritten value in the snapshot mode (see
+    /// [`BarrierMode::Snapshot`]).
+    write_barriers: usize,
+
+    /// The number of fields forwarded by a read barrier triggered by a read of another field of
+    /// the same object (see [`Gc::eager_forward`]).
+    eager_forwards: usize,
+
+    /// The amount of memory allocated since the start of the program.
+    all_time_allocated: usize,
+
+    /// The number of allocated objects (i. e., calls to [`Gc::alloc`]) since the start of the
+    /// program.
+    all_time_allocated_objs: usize,
+
+    /// The amount of memory allocated since the last GC cycle started.
+    allocated_since_gc: usize,
+
+    /// The number of allocated objects since the last GC cycle started.
+    allocated_objs_since_gc: usize,
+
+    /// The amount of memory the collector has copied surviving objects to.
+    evacuated_bytes: usize,
+
+    /// The number of objects the collector has copied to the to-space.
+    evacuated_objs: usize,
+
+    /// The amount of memory the objects moved to the immortal space by [`Gc::promote`] occupy.
+    promoted_bytes: usize,
+
+    /// The number of objects moved to the immortal space by [`Gc::promote`].
+    promoted_objs: usize,
+
+    /// The maximum amount of used memory managed by the GC.
+    max_used: usize,
+
+    /// The maximum amount of memory used in the to-space.
+    max_to_space_used: usize,
+
+    /// The maximum amount of memory held by the semi-spaces at once, which peaks during GC cycles,
+    /// when both of them are allocated.
+    max_combined_used: usize,
+
+    /// The number of bytes lost to alignment: padding inserted in front of over-aligned objects
+    /// and the requested object sizes being rounded up to a multiple of [`ALIGNMENT`].
+    padding_bytes: usize,
+
+    /// The number of times garbage collection took place.
+    ///
+    /// Includes the partical GC cycle when garbage collection is in progress.
+    gc_cycles: usize,
+
+    /// The number of minor collections (see [`Gc::minor_collect`]).
+    minor_cycles: usize,
+
+    /// The number of bytes scanned per allocated byte in the current (or the last) GC cycle.
+    ///
+    /// Chosen at the start of every cycle based on [`Gc::survival_ratio`]: 1 if there's no
+    /// history yet.
+    scan_ratio: f64,
+}
+
+/// A snapshot of the GC statistics exposed to the host (see `gc_get_stats`).
+///
+/// Unlike [`Stats`], it only contains counters and has a stable layout.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy)]
+pub struct GcStatsFfi {
+    /// The number of field reads through `gc_read_barrier`.
+    pub reads: usize,
+
+    /// The number of field writes.
+    pub writes: usize,
+
+    /// The number of field reads that triggered a read barrier.
+    pub read_barriers: usize,
+
+    /// The number of field writes that evacuated the overwritten value in the snapshot mode.
+    pub write_barriers: usize,
+
+    /// The number of fields forwarded eagerly by a read barrier.
+    pub eager_forwards: usize,
+
+    /// The amount of memory allocated since the start of the program.
+    pub allocated_bytes: usize,
+
+    /// The number of objects allocated since the start of the program.
+    pub allocated_objs: usize,
+
+    /// The amount of memory the collector has copied surviving objects to.
+    pub evacuated_bytes: usize,
+
+    /// The number of objects the collector has copied to the to-space.
+    pub evacuated_objs: usize,
+
+    /// The amount of memory the objects promoted to the immortal space occupy.
+    pub promoted_bytes: usize,
+
+    /// The number of objects promoted to the immortal space.
+    pub promoted_objs: usize,
+
+    /// The number of bytes lost to alignment.
+    pub padding_bytes: usize,
+
+    /// The number of GC cycles, including the one in progress, if any.
+    pub gc_cycles: usize,
+
+    /// The number of minor collections.
+    pub minor_cycles: usize,
+
+    /// The maximum amount of used memory managed by the GC.
+    pub max_used: usize,
+
+    /// The maximum amount of memory used in the to-space.
+    pub max_to_space_used: usize,
+}
+
+impl GcStatsFfi {
+    /// Computes the field-wise difference between `after` and `self`, which must be an earlier
+    /// snapshot.
+    ///
+    /// The fields that have decreased (e.g., because the snapshots were passed in the wrong order)
+    /// are set to zero. For the high-water marks, the difference is how much they grew in between.
+    fn delta(&self, after: &Self) -> Self {
+        Self {
+            reads: after.reads.saturating_sub(self.reads),
+            writes: after.writes.saturating_sub(self.writes),
+            read_barriers: after.read_barriers.saturating_sub(self.read_barriers),
+            write_barriers: after.write_barriers.saturating_sub(self.write_barriers),
+            eager_forwards: after.eager_forwards.saturating_sub(self.eager_forwards),
+            allocated_bytes: after.allocated_bytes.saturating_sub(self.allocated_bytes),
+            allocated_objs: after.allocated_objs.saturating_sub(self.allocated_objs),
+            evacuated_bytes: after.evacuated_bytes.saturating_sub(self.evacuated_bytes),
+            evacuated_objs: after.evacuated_objs.saturating_sub(self.evacuated_objs),
+            promoted_bytes: after.promoted_bytes.saturating_sub(self.promoted_bytes),
+            promoted_objs: after.promoted_objs.saturating_sub(self.promoted_objs),
+            padding_bytes: after.padding_bytes.saturating_sub(self.padding_bytes),
+            gc_cycles: after.gc_cycles.saturating_sub(self.gc_cycles),
+            minor_cycles: after.minor_cycles.saturating_sub(self.minor_cycles),
+            max_used: after.max_used.saturating_sub(self.max_used),
+            max_to_space_used: after
+                .max_to_space_used
+                .saturating_sub(self.max_to_space_used),
+        }
+    }
+}
+
+/// A copying semi-space garbage collector.
+struct Gc {
+    /// The from-space.
+    ///
+    /// If garbage collection is not currently underway, this field contains `None`.
+    from_space: Option<Space>,
+
+    /// The to-space.
+    to_space: Space,
+
+    /// The immortal space.
+    ///
+    /// Objects here are never moved or reclaimed, and their fields are treated as roots.
+    immortal: ImmortalSpace,
+
+    /// The root stack.
+    roots: Vec<*mut ObjPtr>,
+
+    /// The roots stored by the GC itself (see [`gc_add_value_root`]), indexed by their handles.
+    ///
+    /// Released roots are `None`.
+    value_roots: Vec<Option<ObjPtr>>,
+
+    /// The handles of the released value roots, to be reused by later additions.
+    free_value_roots: Vec<usize>,
+
+    /// Whether popping from an empty root stack or pushing a null root slot is a fatal error.
+    ///
+    /// If `false`, such pops and pushes are reported and otherwise ignored.
+    strict_roots: bool,
+
+    /// The maximum number of roots forwarded per increment of a GC cycle, or 0 to forward all of
+    /// them when the cycle starts.
+    root_slice: usize,
+
+    /// The index of the first root in the root stack that is yet to be forwarded in the current GC
+    /// cycle.
+    root_cursor: usize,
+
+    /// Whether the current GC cycle has started with some of the roots left unforwarded.
+    ///
+    /// The mutator may then get hold of from-space pointers through them, and store them into the
+    /// objects it allocates during the cycle.
+    roots_deferred: bool,
+
+    /// The copies of the from-space objects whose forwarding pointers have been overwritten by the
+    /// mutator in the Baker and snapshot modes, keyed by the original objects.
+    ///
+    /// This happens when the mutator writes to the first field of an object through a pointer it
+    /// got from a root that hadn't been forwarded yet. Cleared at the end of every GC cycle.
+    clobbered_forwarding: HashMap<ObjPtr, ObjPtr>,
+
+    /// The roots that held something other than a valid object when the current GC cycle started,
+    /// along with that value.
+    ///
+    /// Such roots are left alone by the collector for as long as they hold the same value, rather
+    /// than having the garbage they point to forwarded. Cleared at the end of every GC cycle.
+    #[cfg(feature = "checked")]
+    illegal_roots: HashMap<*mut ObjPtr, ObjPtr>,
+
+    /// The snapshot of the survivors recorded during the current GC cycle, if requested by
+    /// [`Gc::collect_into_snapshot`].
+    snapshot: Option<SnapshotRecorder>,
+
+    /// Whether a garbage collection cycle is currently underway.
+    gc_in_progress: bool,
+
+    /// Whether a summary of every finished GC cycle is printed to stderr.
+    cycle_logging: bool,
+
+    /// Whether every object is checked with [`Gc::validate_object`] at the end of each GC cycle
+    /// (see [`Gc::verify_heap`]).
+    verify_after_collect: bool,
+
+    /// When the current GC cycle started, along with the number of roots at the time and the
+    /// value [`Stats::evacuated_objs`] had.
+    cycle_start: Option<(Instant, usize, usize)>,
+
+    /// The number of outstanding `gc_pin_cycle` calls. While it's non-zero, no collection work is
+    /// done, so objects stay where they are.
+    cycle_pins: usize,
+
+    /// Whether a triggered read barrier forwards all `Obj` fields of the object rather than only
+    /// the field being read.
+    eager_forward: bool,
+
+    /// How the mutator accesses objects while GC is in progress.
+    barrier_mode: BarrierMode,
+
+    /// The order in which evacuated objects are laid out in the to-space.
+    scan_order: ScanOrder,
+
+    /// The end of the scanned area in the to-space.
+    scan: *mut u8,
+
+    /// If GC is undergoing, the end of the area to scan.
+    /// Otherwise, the place to allocate the next object at.
+    next: *mut u8,
+
+    /// If GC is undergoing, the place before which the next object is allocated.
+    /// Otherwise, the end of the free area.
+    limit: *mut u8,
+
+    /// The number of bytes the collector has yet to scan to keep pace with the allocations made
+    /// since the current GC cycle started.
+    scan_debt: usize,
+
+    /// The number of steps (increments and scanned objects) taken in the current GC cycle.
+    cycle_steps: usize,
+
+    /// The number of steps after which the current GC cycle is considered stuck, not counting the
+    /// roots (see [`Gc::tick_watchdog`]).
+    cycle_in_progress_deadline: usize,
+
+    /// The exponential moving average of the fraction of the used memory that survived a GC
+    /// cycle, or `None` if no cycle has finished yet.
+    survival_ratio: Option<f64>,
+
+    /// The amount of memory used in the from-space when the current GC cycle started.
+    cycle_used: usize,
+
+    /// When allocation starts a GC cycle.
+    collection_policy: CollectionPolicy,
+
+    /// The number of bytes that survived the last finished GC cycle, or `None` if there hasn't
+    /// been one yet.
+    last_survived: Option<usize>,
+
+    /// Metadata of objects that are over-aligned or have been allocated in a non-zero epoch.
+    ///
+    /// Keyed by the object's current address; the entries are moved along with the objects when
+    /// they're evacuated. Objects without an entry have the default [`ObjInfo`].
+    obj_info: HashMap<*mut StellaObj, ObjInfo>,
+
+    /// The start of the region eligible for minor collection.
+    ///
+    /// Everything allocated below this point is considered old. Set to the value of `next` at the
+    /// end of every collection.
+    minor_floor: *mut u8,
+
+    /// The fields that had a pointer into the region eligible for minor collection stored in them
+    /// via the write barrier, as `(object, field index)` pairs.
+    ///
+    /// Cleared when a collection starts.
+    remembered: HashSet<(ObjPtr, usize)>,
+
+    /// The fields that had a from-space pointer stored in them during the current GC cycle after
+    /// their objects had been scanned, as `(object, field index)` pairs.
+    ///
+    /// This happens to `Ref` objects, and to any object while [`Gc::roots_deferred`] is set. The
+    /// fields are forwarded again before the from-space is released.
+    dirty_fields: Vec<(ObjPtr, usize)>,
+
+    /// The conditionally traced fields of objects with a given tag, indexed by the tag.
+    ///
+    /// The key field doesn't keep anything alive, and the value field is only traced once the
+    /// key is found to be reachable by other means. If it's not by the end of a GC cycle, both
+    /// fields are cleared. Minor collections treat these as ordinary fields.
+    conditional_fields: [Option<ConditionalField>; StellaTag::COUNT],
+
+    /// The scanned objects whose conditional value field is waiting for its key to be reached in
+    /// the current GC cycle.
+    pending_conditional: Vec<(ObjPtr, ConditionalField)>,
+
+    /// The current allocation epoch.
+    ///
+    /// Each object is stamped with the epoch it was allocated in, which lets the host enumerate
+    /// the objects allocated since a given point in time.
+    epoch: u64,
+
+    /// The size of the to-space allocated at the start of the next GC cycle.
+    space_size: usize,
+
+    /// The size the semi-spaces grow to once a cycle leaves them more than half full.
+    ///
+    /// Keeps a heap that is too small for the live set from collecting on nearly every allocation.
+    min_heap: usize,
+
+    /// The share of the to-space (in percent) that must be left free after a GC cycle.
+    ///
+    /// If there's less free memory at the end of a cycle, the semi-spaces grow so that the next
+    /// cycle doesn't have to start right away. 0 disables the check.
+    survivor_headroom: u8,
+
+    /// The callbacks to run when the collector is shut down, in the order of registration.
+    exit_callbacks: Vec<extern "C" fn()>,
+
+    /// The callback to notify of memory pressure, along with the to-space occupancy (in percent)
+    /// at which it fires.
+    pressure_callback: Option<(u8, extern "C" fn(used: usize, total: usize))>,
+
+    /// Whether the memory pressure callback has fired since the last GC cycle started.
+    pressure_latched: bool,
+
+    /// The arguments of the memory pressure callback call that is yet to be made once the lock is
+    /// released.
+    pending_pressure: Option<(usize, usize)>,
+
+    /// The callback to notify once a GC cycle completes.
+    post_gc_callback: Option<PostGcCallback>,
+
+    /// The arguments of the post-GC callback calls that are yet to be made once the lock is
+    /// released, one per completed cycle.
+    pending_post_gc: Vec<(usize, usize, usize, usize)>,
+
+    /// The finalizers of the objects that are still alive, keyed by the object's current address.
+    ///
+    /// The keys are updated once a collection finishes moving objects around.
+    finalizers: HashMap<*mut StellaObj, Finalizer>,
+
+    /// Whether the finalizers of objects dying together run for the referenced objects before the
+    /// objects referencing them, rather than the other way around.
+    finalize_referents_first: bool,
+
+    /// The finalizers of dead objects that are yet to be run once the lock is released, in order.
+    ready_finalizers: Vec<Finalizer>,
+
+    /// The description of the most recent recoverable failure.
+    last_error: Option<CString>,
+
+    /// The host-provided function rendering `Raw` fields in object dumps.
+    ///
+    /// It's called with the object, the field index, and a buffer of [`RAW_FIELD_BUF_SIZE`] bytes to
+    /// write a NUL-terminated string to.
+    raw_field_formatter: Option<extern "C" fn(ObjPtr, c_int, *mut c_char, usize)>,
+
+    /// The range of words scanned for conservative roots at the start of every GC cycle.
+    #[cfg(feature = "conservative")]
+    stack_bounds: Option<(*const usize, *const usize)>,
+
+    /// The from-space objects referenced by conservative roots, which stay in place during the
+    /// current GC cycle.
+    #[cfg(feature = "conservative")]
+    pinned: HashSet<ObjPtr>,
+
+    /// A caller-provided semi-space to use as the next to-space instead of allocating one.
+    ///
+    /// Holds the released from-space when the GC runs on fixed spaces.
+    #[cfg(feature = "testing")]
+    spare_space: Option<Space>,
+
+    /// Whether to overwrite the from-space with [`POISON_BYTE`] before it's released.
+    #[cfg(feature = "poison")]
+    poison_from_space: bool,
+
+    /// The most recent GC events in chronological order, capped at [`EVENT_LOG_CAPACITY`].
+    #[cfg(feature = "event-log")]
+    event_log: VecDeque<GcEvent>,
+
+    /// Garbage collection statistics.
+    stats: Stats,
+}
+
+unsafe impl Send for Gc {}
+
+impl Gc {
+    /// Creates a new garbage collector instance.
+    ///
+    /// The size of the semi-spaces is taken from `max_alloc_size`.
+    ///
+    /// # Panics
+    /// Panics if `max_alloc_size` is less than [`MIN_SPACE_SIZE`] or doesn't fit in `usize`.
+    ///
+    /// # Safety
+    /// The external variables must have already been initialized to valid values.
+    pub unsafe fn new() -> Self {
+        let size = unsafe { max_alloc_size };
+        let size = usize::try_from(size).unwrap_or_else(|_| {
+            panic!("max_alloc_size is set to {size} B, which exceeds the address space")
+        });
+        assert!(
+            size >= MIN_SPACE_SIZE,
+            "max_alloc_size is set to {size} B, but the heap needs at least {MIN_SPACE_SIZE} B; \
+             is it initialized?",
+        );
+
+        Self::with_to_space(Space::alloc(size))
+    }
+
+    /// Creates a new garbage collector instance whose semi-spaces are the caller-provided buffers
+    /// of `size` bytes at `from_base` and `to_base`, which it alternates between.
+    ///
+    /// The addresses of allocated objects are entirely determined by the buffers' addresses, which
+    /// makes the collector's behavior reproducible.
+    ///
+    /// # Panics
+    /// Panics if the buffers aren't aligned to [`SPACE_ALIGNMENT`], or `size` isn't a multiple of
+    /// [`ALIGNMENT`].
+    ///
+    /// # Safety
+    /// The buffers must not overlap, and they must remain valid and otherwise unused for as long as
+    /// the collector lives.
+    #[cfg(feature = "testing")]
+    pub unsafe fn with_fixed_spaces(from_base: *mut u8, to_base: *mut u8, size: usize) -> Self {
+        let mut result = Self::with_to_space(unsafe { Space::borrowed(to_base, size) });
+        result.spare_space = Some(unsafe { Space::borrowed(from_base, size) });
+
+        result
+    }
+
+    /// Creates a new garbage collector instance with the given initial to-space.
+    fn with_to_space(to_space: Space) -> Self {
+        publish_from_space(None);
+
+        let next = to_space.start;
+        let limit = to_space.end();
+        let space_size = to_space.size;
+
+        Self {
+            from_space: None,
+            to_space,
+            immortal: Default::default(),
+
+            roots: Default::default(),
+            value_roots: Default::default(),
+            free_value_roots: Default::default(),
+            strict_roots: true,
+            root_slice: DEFAULT_ROOT_SLICE,
+            root_cursor: 0,
+            roots_deferred: false,
+            clobbered_forwarding: Default::default(),
+            #[cfg(feature = "checked")]
+            illegal_roots: Default::default(),
+            snapshot: None,
+
+            gc_in_progress: false,
+            cycle_logging: false,
+            verify_after_collect: false,
+            cycle_start: None,
+            cycle_pins: 0,
+            eager_forward: false,
+            barrier_mode: Default::default(),
+            scan_order: Default::default(),
+            scan: Default::default(),
+            next,
+            limit,
+            scan_debt: 0,
+            cycle_steps: 0,
+            cycle_in_progress_deadline: 0,
+            survival_ratio: None,
+            cycle_used: 0,
+            collection_policy: Default::default(),
+            last_survived: None,
+
+            obj_info: Default::default(),
+            minor_floor: next,
+            remembered: Default::default(),
+            dirty_fields: Default::default(),
+            conditional_fields: [None; StellaTag::COUNT],
+            pending_conditional: Default::default(),
+            epoch: 0,
+
+            space_size,
+            min_heap: 0,
+            survivor_headroom: 0,
+            exit_callbacks: Default::default(),
+            pressure_callback: None,
+            pressure_latched: false,
+            pending_pressure: None,
+            post_gc_callback: None,
+            pending_post_gc: vec![],
+            finalizers: Default::default(),
+            finalize_referents_first: false,
+            ready_finalizers: Default::default(),
+            last_error: None,
+            raw_field_formatter: None,
+
+            #[cfg(feature = "conservative")]
+            stack_bounds: None,
+            #[cfg(feature = "conservative")]
+            pinned: Default::default(),
+
+            #[cfg(feature = "testing")]
+            spare_space: None,
+
+            #[cfg(feature = "poison")]
+            poison_from_space: true,
+
+            #[cfg(feature = "event-log")]
+            event_log: VecDeque::with_capacity(EVENT_LOG_CAPACITY),
+
+            stats: Stats {
+                scan_ratio: 1.0,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Allocates a new object of the given size and alignment at `self.next`.
+    ///
     /// Returns `None` if there's not enough free memory in the to-space.
     ///
     /// # Safety
-    /// Must only be called when `self.gc_in_progress` is `false`.
-    unsafe fn alloc_at_next(&mut self, size: usize) -> Option<ObjPtr> {
-        if !self.limit.is_null() && self.next.wrapping_byte_add(size) < self.limit {
-            let result = ObjPtr(self.next.cast());
-            self.next = unsafe { self.next.byte_add(size) };
+    /// Must only be called when `self.gc_in_progress` is `false`. `align` must be a power of two not
+    /// exceeding [`SPACE_ALIGNMENT`].
+    unsafe fn alloc_at_next(&mut self, size: usize, align: usize) -> Option<ObjPtr> {
+        let start = self.place_at_next(size, align)?;
+
+        unsafe { fill_padding(self.next, start) };
+        if stats_enabled() {
+            self.stats.padding_bytes += unsafe { start.byte_offset_from_unsigned(self.next) };
+        }
+
+        self.next = unsafe { start.byte_add(size) };
+
+        Some(ObjPtr(start.cast()))
+    }
+
+    /// Computes where [`Gc::alloc_at_next`] would place an object of the given size and alignment.
+    ///
+    /// Returns `None` if there's not enough free memory in the to-space.
+    fn place_at_next(&self, size: usize, align: usize) -> Option<*mut u8> {
+        let start = self
+            .next
+            .wrapping_byte_add(align_up(self.next.addr(), align) - self.next.addr());
+
+        (!self.limit.is_null() && start.wrapping_byte_add(size) < self.limit).then_some(start)
+    }
+
+    /// Returns `true` if allocating an object of the given size right now would start a GC cycle.
+    fn would_trigger_gc(&self, size: usize) -> bool {
+        let size = self.footprint_for(obj_size_for(size));
+
+        !self.gc_in_progress
+            && self.collection_policy != CollectionPolicy::Manual
+            && (self.policy_wants_gc(size) || self.place_at_next(size, ALIGNMENT).is_none())
+    }
+
+    /// Returns `true` if the collection policy asks for a GC cycle to start before allocating
+    /// `size` more bytes, even though they may still fit in the to-space.
+    fn policy_wants_gc(&self, size: usize) -> bool {
+        match self.collection_policy {
+            CollectionPolicy::WhenFull | CollectionPolicy::Manual => false,
+
+            CollectionPolicy::EveryNBytes(n) => {
+                self.stats.allocated_since_gc.saturating_add(size) > n
+            }
+
+            CollectionPolicy::EveryNAllocations(n) => self.stats.allocated_objs_since_gc >= n,
+        }
+    }
+
+    /// Returns the number of bytes an object of the given size occupies in a semi-space.
+    ///
+    /// In the Brooks mode, this includes the object's forwarding word.
+    fn footprint_for(&self, size: usize) -> usize {
+        match self.barrier_mode {
+            BarrierMode::Baker | BarrierMode::Snapshot => size,
+            BarrierMode::Brooks => size.saturating_add(FIELD_SIZE),
+        }
+    }
+
+    /// Returns the number of bytes an object or a padding filler in a semi-space occupies.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object or a padding filler.
+    unsafe fn footprint(&self, ptr: ObjPtr) -> usize {
+        if unsafe { ptr.is_padding() } {
+            PADDING_SIZE
+        } else {
+            self.footprint_for(unsafe { ptr.size() })
+        }
+    }
+
+    /// Returns a pointer to the forwarding word of a semi-space object in the Brooks mode.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object in a semi-space.
+    unsafe fn forwarding_word(ptr: ObjPtr) -> *mut ObjPtr {
+        unsafe { ptr.0.byte_add(ptr.size()) }.cast()
+    }
+
+    /// Returns the forwarding pointer of a from-space object.
+    ///
+    /// Unless the object has been evacuated, the result is unspecified in the Baker mode, and is
+    /// `ptr` itself in the Brooks mode.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object in the from-space.
+    unsafe fn forwardee(&self, ptr: ObjPtr) -> ObjPtr {
+        match self.barrier_mode {
+            BarrierMode::Baker | BarrierMode::Snapshot => {
+                if !self.clobbered_forwarding.is_empty()
+                    && let Some(&copy) = self.clobbered_forwarding.get(&ptr)
+                {
+                    return copy;
+                }
+
+                unsafe { *ptr.field(0) }
+            }
+
+            BarrierMode::Brooks => unsafe { *Self::forwarding_word(ptr) },
+        }
+    }
+
+    /// Returns the current location of the object, following its forwarding word in the Brooks
+    /// mode if it's in the from-space.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn resolve(&self, ptr: ObjPtr) -> ObjPtr {
+        if self.barrier_mode == BarrierMode::Brooks
+            && self
+                .from_space
+                .as_ref()
+                .is_some_and(|from_space| from_space.contains(ptr.0.cast()))
+        {
+            unsafe { self.forwardee(ptr) }
+        } else {
+            ptr
+        }
+    }
+
+    /// Switches to another barrier mode.
+    ///
+    /// # Panics
+    /// Panics if the semi-spaces aren't empty, since the modes lay objects out differently.
+    fn set_barrier_mode(&mut self, mode: BarrierMode) {
+        assert!(
+            !self.gc_in_progress
+                && self.next == self.to_space.start
+                && self.limit == self.to_space.end(),
+            "cannot change the barrier mode once objects have been allocated",
+        );
+
+        #[cfg(feature = "conservative")]
+        assert!(
+            mode != BarrierMode::Brooks || self.stack_bounds.is_none(),
+            "conservative stack scanning is incompatible with the Brooks mode",
+        );
+
+        self.barrier_mode = mode;
+    }
+
+    /// Appends an event to the event log, discarding the oldest one if it's full.
+    ///
+    /// The event is only constructed if the `event-log` feature is enabled.
+    fn log_event(&mut self, event: impl FnOnce(&Self) -> GcEvent) {
+        #[cfg(feature = "event-log")]
+        {
+            let event = event(self);
+
+            if self.event_log.len() == EVENT_LOG_CAPACITY {
+                self.event_log.pop_front();
+            }
+
+            self.event_log.push_back(event);
+        }
+
+        #[cfg(not(feature = "event-log"))]
+        let _ = event;
+    }
+
+    /// Records a new allocation of `size` bytes at `ptr` for the stats.
+    fn register_alloc(&mut self, ptr: ObjPtr, size: usize) {
+        self.log_event(|gc| GcEvent::Alloc {
+            size,
+            at: gc.classify_space(ptr.0),
+        });
+        // the object and byte counts since the last cycle drive the collection policy, and the
+        // all-time object count is part of the mutation stamp, so they're always tracked.
+        self.stats.all_time_allocated_objs += 1;
+        self.stats.allocated_since_gc += size;
+        self.stats.allocated_objs_since_gc += 1;
+
+        if stats_enabled() {
+            self.stats.all_time_allocated += size;
+        }
+
+        self.update_max_used();
+
+        if let Some((threshold, _)) = self.pressure_callback
+            && !self.pressure_latched
+        {
+            let used = self.to_space_used_memory();
+            let total = self.to_space.size;
+
+            if used.saturating_mul(100) >= total.saturating_mul(threshold.into()) {
+                self.pressure_latched = true;
+                self.pending_pressure = Some((used, total));
+            }
+        }
+
+        self.check_pointers();
+    }
+
+    /// Updates the memory usage high-water marks in the stats.
+    fn update_max_used(&mut self) {
+        if !stats_enabled() {
+            return;
+        }
+
+        let combined = self
+            .from_space
+            .as_ref()
+            .map_or(0, |from_space| from_space.size)
+            + self.to_space.size;
+
+        self.stats.max_used = self.stats.max_used.max(self.used_memory());
+        self.stats.max_to_space_used = self
+            .stats
+            .max_to_space_used
+            .max(self.to_space_used_memory());
+        self.stats.max_combined_used = self.stats.max_combined_used.max(combined);
+    }
+
+    /// Allocates a new object of the given size.
+    ///
+    /// Starts a GC cycle if it's deemed necessary.
+    ///
+    /// # Panics
+    /// Panics if there's not enough memory (see [`Gc::try_alloc_aligned`]).
+    ///
+    /// # Safety
+    /// The size must be non-zero.
+    pub unsafe fn alloc(&mut self, size: usize) -> ObjPtr {
+        unsafe { self.alloc_aligned(size, ALIGNMENT) }
+    }
+
+    /// Allocates a new object of the given size whose address is a multiple of `align`.
+    ///
+    /// Alignments up to [`ALIGNMENT`] are free. Stricter alignments cost the padding needed to
+    /// reach the next suitable address, both here and every time the object is evacuated, as well
+    /// as an entry in [`Gc::obj_info`] for as long as the object lives.
+    ///
+    /// Starts a GC cycle if it's deemed necessary.
+    ///
+    /// # Panics
+    /// Panics if there's not enough memory (see [`Gc::try_alloc_aligned`]), or if `align` is not a
+    /// power of two or exceeds [`SPACE_ALIGNMENT`].
+    ///
+    /// # Safety
+    /// The size must be non-zero.
+    pub unsafe fn alloc_aligned(&mut self, size: usize, align: usize) -> ObjPtr {
+        let result = unsafe { self.try_alloc_aligned(size, align) };
+
+        result.unwrap_or_else(|| {
+            panic!(
+                "{}",
+                self.last_error
+                    .as_deref()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+            )
+        })
+    }
+
+    /// Allocates a new object of the given size at the high end of the to-space's free area, even if
+    /// GC is not in progress.
+    ///
+    /// The object is an ordinary one otherwise: it survives the next GC cycle if it's reachable
+    /// when the cycle gets to it. Since it sits above [`Gc::limit`], minor collections never
+    /// reclaim it. If it doesn't fit in the free area, this falls back to [`Gc::alloc`], starting a
+    /// GC cycle if necessary.
+    ///
+    /// # Panics
+    /// Panics if there's not enough memory (see [`Gc::try_alloc_aligned`]).
+    ///
+    /// # Safety
+    /// The size must be non-zero.
+    unsafe fn alloc_transient(&mut self, size: usize) -> ObjPtr {
+        let obj_size = obj_size_for(size);
+        let footprint = self.footprint_for(obj_size);
+
+        if self.gc_in_progress
+            || self.limit.is_null()
+            || footprint >= self.limit.addr() - self.next.addr()
+        {
+            return unsafe { self.alloc(size) };
+        }
+
+        self.limit = unsafe { self.limit.byte_sub(footprint) };
+        let result = ObjPtr(self.limit.cast());
+        unsafe { self.init_forwarding_word(result, obj_size) };
+        unsafe { Self::clear_forwarding_slot(result) };
+        if stats_enabled() {
+            self.stats.padding_bytes += obj_size - size;
+        }
+
+        self.register_alloc(result, footprint);
+        self.register_obj_info(result, ALIGNMENT);
+
+        result
+    }
+
+    /// Same as [`Gc::alloc_aligned`], but returns `None` if there's not enough free memory while GC
+    /// is in progress, or if a GC cycle would have to start but collection is suppressed (see
+    /// [`Gc::cycle_pins`]) or the new to-space can't be allocated.
+    ///
+    /// An object that wouldn't fit even in an empty semi-space is rejected right away, without
+    /// starting a GC cycle that couldn't help. The reason of a failure is recorded in
+    /// [`Gc::last_error`].
+    ///
+    /// # Panics
+    /// Panics if `align` is not a power of two or exceeds [`SPACE_ALIGNMENT`], or if the survivors
+    /// don't fit in the to-space.
+    ///
+    /// # Safety
+    /// The size must be non-zero.
+    unsafe fn try_alloc_aligned(&mut self, size: usize, align: usize) -> Option<ObjPtr> {
+        assert!(
+            align.is_power_of_two() && align <= SPACE_ALIGNMENT,
+            "invalid alignment: {align}",
+        );
+
+        let max_size = self.space_size.max(self.to_space.size);
+        // an overflowing size is too large for any heap.
+        let obj_size = obj_size_for(size);
+        let rounding = obj_size.wrapping_sub(size);
+        let size = self.footprint_for(obj_size);
+        let align = align.max(ALIGNMENT);
+
+        if size > max_size {
+            self.set_last_error(format_args!(
+                "object too large for the heap: {size} B exceeds the semi-space size of {max_size} B",
+            ));
+
+            return None;
+        }
+
+        if !self.gc_in_progress && self.cycle_pins == 0 && self.policy_wants_gc(size) {
+            // if the to-space can't be allocated, the object may still fit in the current one.
+            unsafe { self.begin_gc() };
+        }
+
+        if !self.gc_in_progress {
+            let result = unsafe { self.alloc_at_next(size, align) }.or_else(|| {
+                self.grow_to_space_in_place()
+                    .then(|| unsafe { self.alloc_at_next(size, align) })
+                    .flatten()
+            });
+
+            if let Some(result) = result {
+                unsafe { self.init_forwarding_word(result, obj_size) };
+                unsafe { Self::clear_forwarding_slot(result) };
+                if stats_enabled() {
+                    self.stats.padding_bytes += rounding;
+                }
+
+                self.register_alloc(result, size);
+                self.register_obj_info(result, align);
+
+                return Some(result);
+            }
+
+            if self.collection_policy == CollectionPolicy::Manual {
+                self.set_last_error(format_args!(
+                    "out of memory: cannot allocate {size} B until garbage is collected",
+                ));
+
+                return None;
+            }
+
+            if self.cycle_pins > 0 || !unsafe { self.begin_gc() } {
+                self.set_last_error(format_args!("out of memory: cannot allocate {size} B"));
+
+                return None;
+            }
+        }
+
+        let result = self.limit.wrapping_byte_sub(size);
+        let result = result.wrapping_byte_sub(result.addr() % align);
+
+        if self.limit.is_null() || self.next.is_null() || result < self.next || result > self.limit
+        {
+            self.set_last_error(format_args!("out of memory: cannot allocate {size} B"));
+
+            return None;
+        }
+
+        unsafe { fill_padding(result.byte_add(size), self.limit) };
+        if stats_enabled() {
+            self.stats.padding_bytes +=
+                unsafe { self.limit.byte_offset_from_unsigned(result) } - size;
+        }
+
+        self.limit = result;
+
+        // `run_gc` may walk the objects allocated during the cycle, but this one isn't initialized
+        // until we return.
+        if cfg!(feature = "verify-after-gc")
+            || self.verify_after_collect
+            || self.barrier_mode == BarrierMode::Brooks
+            || self.roots_deferred
+        {
+            unsafe { fill_padding(result, result.byte_add(size)) };
+        }
+
+        unsafe { self.pace_gc(size) };
+        unsafe { self.init_forwarding_word(ObjPtr(result.cast()), obj_size) };
+        unsafe { Self::clear_forwarding_slot(ObjPtr(result.cast())) };
+        if stats_enabled() {
+            self.stats.padding_bytes += rounding;
+        }
+
+        self.register_alloc(ObjPtr(result.cast()), size);
+        self.register_obj_info(ObjPtr(result.cast()), align);
+
+        Some(ObjPtr(result.cast()))
+    }
+
+    /// Allocates a new object with the given tag and number of fields and initializes its header.
+    ///
+    /// The fields are set to null.
+    ///
+    /// Starts a GC cycle if it's deemed necessary.
+    ///
+    /// Returns `None` and records the reason in [`Gc::last_error`] if `field_count` doesn't match
+    /// the tag's arity or can't be stored in the header, or if there's not enough free memory while
+    /// GC is in progress.
+    fn alloc_tagged(&mut self, tag: StellaTag, field_count: usize) -> Option<ObjPtr> {
+        if let Some(arity) = tag.arity()
+            && field_count != arity
+        {
+            self.set_last_error(format_args!(
+                "an object tagged `{tag}` must have {arity} fields (got {field_count})",
+            ));
+
+            return None;
+        }
+
+        let max_field_count = unsafe { FIELD_COUNT_MASK as usize } >> 4;
+
+        if field_count > max_field_count {
+            self.set_last_error(format_args!(
+                "an object cannot have more than {max_field_count} fields (got {field_count})",
+            ));
+
+            return None;
+        }
+
+        let size = field_offset(field_count).unwrap();
+
+        let result = unsafe { self.try_alloc_aligned(size, ALIGNMENT) }?;
+
+        unsafe {
+            ptr::write(
+                &raw mut (*result.0).header,
+                tag as Header | (field_count as Header) << 4,
+            );
+
+            for idx in 0..field_count {
+                ptr::write(result.field(idx), ObjPtr(ptr::null_mut()));
+            }
+        }
+
+        Some(result)
+    }
+
+    /// Allocates a new object with the given tag, initializing its header and fields with `fields`.
+    ///
+    /// The object is complete by the time a GC cycle could see it. The `Obj` values are kept
+    /// alive (and updated if they're moved) during the allocation.
+    ///
+    /// Returns `None` under the same conditions as [`Gc::alloc_tagged`].
+    ///
+    /// # Safety
+    /// The values must be valid for their fields.
+    unsafe fn alloc_with_fields(&mut self, tag: StellaTag, fields: &[ObjPtr]) -> Option<ObjPtr> {
+        let mut values = fields.to_vec();
+        let roots_len = self.roots.len();
+
+        // raw fields aren't pointers, so forwarding them would corrupt them.
+        self.roots.extend(
+            values
+                .iter_mut()
+                .enumerate()
+                .filter(|&(idx, _)| tag.field_kind(idx) == StellaFieldKind::Obj)
+                .map(|(_, value)| ptr::from_mut(value)),
+        );
+
+        let result = self.alloc_tagged(tag, values.len());
+        self.roots.truncate(roots_len);
+        let result = result?;
+
+        for (idx, value) in values.into_iter().enumerate() {
+            unsafe { ptr::write(result.field(idx), value) };
+        }
+
+        Some(result)
+    }
+
+    /// Records the description of a recoverable failure, replacing the previous one.
+    fn set_last_error(&mut self, msg: impl Display) {
+        self.last_error = Some(CString::new(msg.to_string()).unwrap());
+    }
+
+    /// Points the forwarding word of a freshly allocated object of the given size to the object
+    /// itself in the Brooks mode.
+    ///
+    /// # Safety
+    /// The memory for the forwarding word must have been allocated along with the object.
+    unsafe fn init_forwarding_word(&self, ptr: ObjPtr, size: usize) {
+        if self.barrier_mode == BarrierMode::Brooks {
+            unsafe { ptr::write(ptr.0.byte_add(size).cast(), ptr) };
+        }
+    }
+
+    /// Nulls the first field of a freshly allocated object.
+    ///
+    /// The mutator never writes it if the object has no fields, and whatever was left in memory
+    /// there could then be mistaken for a forwarding pointer.
+    ///
+    /// # Safety
+    /// The object must have been allocated with at least [`MIN_OBJ_SIZE`] bytes.
+    unsafe fn clear_forwarding_slot(ptr: ObjPtr) {
+        unsafe { ptr::write(ptr.field(0), ObjPtr(ptr::null_mut())) };
+    }
+
+    /// Records the metadata of a freshly allocated object unless it's the default.
+    fn register_obj_info(&mut self, ptr: ObjPtr, align: usize) {
+        if align > ALIGNMENT || self.epoch > 0 {
+            let epoch = self.epoch;
+            self.obj_info.insert(ptr.0, ObjInfo { align, epoch });
+        }
+    }
+
+    /// Allocates a new object of the given size in the immortal space.
+    ///
+    /// The object is never moved or reclaimed. Its fields are forwarded at the start of every GC
+    /// cycle, so it keeps everything it references alive.
+    fn alloc_immortal(&mut self, size: usize) -> ObjPtr {
+        let obj_size = align_up(size.max(MIN_OBJ_SIZE), ALIGNMENT);
+        let result = ObjPtr(self.immortal.alloc(obj_size).cast());
+        if stats_enabled() {
+            self.stats.padding_bytes += obj_size - size;
+        }
+
+        self.register_alloc(result, obj_size);
+        self.register_obj_info(result, ALIGNMENT);
+
+        result
+    }
+
+    /// Tries to grow the to-space to the size the next GC cycle would allocate without moving it,
+    /// which makes the cycle unnecessary for now.
+    ///
+    /// Returns `true` if the to-space has grown. Only possible outside a GC cycle, when the heap
+    /// is supposed to grow, and when there are no objects at the high end of the to-space (left
+    /// there by the previous cycle or [`Gc::alloc_transient`]), which would otherwise separate the
+    /// new memory from the free area.
+    fn grow_to_space_in_place(&mut self) -> bool {
+        if self.gc_in_progress
+            || self.space_size <= self.to_space.size
+            || self.limit != self.to_space.end()
+            || !self.to_space.grow_in_place(self.space_size)
+        {
+            return false;
+        }
+
+        self.limit = self.to_space.end();
+        self.update_max_used();
+
+        true
+    }
+
+    /// Returns the memory for the to-space of the next GC cycle, or `None` if it can't be
+    /// allocated.
+    ///
+    /// If the semi-spaces are supposed to grow but the system allocator can't provide that much
+    /// memory, a to-space of the current size is allocated instead.
+    fn next_to_space(&mut self) -> Option<Space> {
+        let new_size = self.space_size;
+
+        #[cfg(feature = "testing")]
+        if self
+            .spare_space
+            .as_ref()
+            .is_some_and(|space| space.size == new_size)
+        {
+            return self.spare_space.take();
+        }
+
+        let current_size = self.to_space.size;
+        let space = Space::try_alloc(new_size).or_else(|| {
+            (new_size > current_size)
+                .then(|| Space::try_alloc(current_size))
+                .flatten()
+        })?;
+
+        #[cfg(feature = "testing")]
+        {
+            self.spare_space = None;
+        }
+
+        Some(space)
+    }
+
+    /// Starts a new GC cycle.
+    ///
+    /// Returns `false` if there's not enough memory for the new to-space (see
+    /// [`Gc::next_to_space`]), in which case the cycle isn't started and nothing is changed.
+    ///
+    /// # Safety
+    /// This method must only be called if GC is not currently underway. All roots must have already
+    /// been registered in the root stack.
+    unsafe fn begin_gc(&mut self) -> bool {
+        let Some(to_space) = self.next_to_space() else {
+            return false;
+        };
+
+        self.gc_in_progress = true;
+        self.stats.gc_cycles += 1;
+        self.cycle_start = Some((Instant::now(), self.roots.len(), self.stats.evacuated_objs));
+        self.log_event(|gc| GcEvent::CycleStart {
+            cycle: gc.stats.gc_cycles,
+        });
+        self.remembered.clear();
+        self.pressure_latched = false;
+        self.scan_debt = 0;
+        self.cycle_steps = 0;
+        // every increment scans at least one object, and the to-space can only hold so many.
+        self.cycle_in_progress_deadline =
+            (2 * (to_space.size / ALIGNMENT)).saturating_add(CYCLE_STEP_SLACK);
+        self.cycle_used = self.to_space_used_memory();
+        self.stats.allocated_since_gc = 0;
+        self.stats.allocated_objs_since_gc = 0;
+        self.stats.scan_ratio = self.survival_ratio.map_or(1.0, |ratio| {
+            // the expected survivors have to be scanned before the mutator fills the rest of the
+            // to-space.
+            let expected = ratio * self.cycle_used as f64;
+            let free = (to_space.size as f64 - expected).max(1.0);
+
+            (expected / free).clamp(SCAN_RATIO_RANGE.0, SCAN_RATIO_RANGE.1)
+        });
+
+        // the to-space is about to become the from-space, where allocated areas can't be told apart
+        // from the free ones anymore.
+        #[cfg(feature = "checked")]
+        self.find_illegal_roots();
+
+        #[cfg(feature = "conservative")]
+        let from_free = (self.next, self.limit);
+        self.from_space = Some(mem::replace(&mut self.to_space, to_space));
+
+        if let Some(from_space) = &self.from_space {
+            debug_assert!(
+                !from_space.overlaps(&self.to_space),
+                "the semi-spaces overlap: from-space {:?}..{:?}, to-space {:?}..{:?}",
+                from_space.start,
+                from_space.end(),
+                self.to_space.start,
+                self.to_space.end(),
+            );
+        }
+
+        self.next = self.to_space.start;
+        self.scan = self.to_space.start;
+        self.limit = self.to_space.end();
+        publish_from_space(self.from_space.as_ref());
+        self.update_max_used();
+
+        #[cfg(feature = "conservative")]
+        unsafe {
+            self.pin_stack_referents(from_free)
+        };
+
+        self.root_cursor = 0;
+        unsafe { self.forward_value_roots() };
+        unsafe { self.forward_root_slice() };
+        self.roots_deferred = self.root_cursor < self.roots.len();
+        unsafe { self.forward_immortal_fields() };
+        self.check_pointers();
+
+        true
+    }
+
+    /// Forwards the fields of every immortal object.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle.
+    unsafe fn forward_immortal_fields(&mut self) {
+        let immortal = mem::take(&mut self.immortal);
+
+        immortal.for_each_object(|ptr| unsafe { self.forward_obj_fields(ptr) });
+        self.immortal = immortal;
+    }
+
+    /// Returns the conditional field rule that applies to the object, if any.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object or a padding filler.
+    unsafe fn conditional_field(&self, ptr: ObjPtr) -> Option<ConditionalField> {
+        let rule = self.conditional_fields[unsafe { ptr.try_tag() }? as usize]?;
+        let field_count = unsafe { ptr.field_count() };
+
+        (rule.value_idx < field_count && rule.key_idx < field_count).then_some(rule)
+    }
+
+    /// Forwards the fields of a scanned object.
+    ///
+    /// The fields covered by a conditional field rule are only forwarded if the key is reachable;
+    /// otherwise, the object is added to [`Gc::pending_conditional`].
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. `ptr` must point to a valid stella object
+    /// that is not going to move until the end of the cycle.
+    unsafe fn forward_obj_fields(&mut self, ptr: ObjPtr) {
+        let rule = unsafe { self.conditional_field(ptr) };
+        let tag = unsafe { ptr.try_tag() };
+
+        for idx in 0..unsafe { ptr.field_count() } {
+            // raw payloads may alias the from-space by coincidence, like in the read barrier.
+            if rule.is_some_and(|rule| rule.covers(idx))
+                || tag.is_none_or(|tag| tag.field_kind(idx) != StellaFieldKind::Obj)
+            {
+                continue;
+            }
+
+            let field_ptr = unsafe { ptr.field(idx) };
+
+            unsafe { ptr::write(field_ptr, self.forward(*field_ptr)) };
+        }
+
+        if let Some(rule) = rule
+            && !unsafe { self.trace_conditional(ptr, rule) }
+        {
+            self.pending_conditional.push((ptr, rule));
+        }
+    }
+
+    /// Forwards the key and the value of a conditional field rule if the key is reachable.
+    ///
+    /// Returns `false` if the key hasn't been reached (yet), in which case nothing is done.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. `ptr` must point to a valid stella
+    /// object the rule applies to.
+    unsafe fn trace_conditional(&mut self, ptr: ObjPtr, rule: ConditionalField) -> bool {
+        let key = unsafe { *ptr.field(rule.key_idx) };
+
+        let reached = !self
+            .from_space
+            .as_ref()
+            .is_some_and(|from_space| from_space.contains(key.0.cast()))
+            || self.is_pinned(key)
+            || self
+                .to_space
+                .contains(unsafe { self.forwardee(key) }.0.cast());
+
+        if reached {
+            unsafe {
+                ptr::write(ptr.field(rule.key_idx), self.forward(key));
+                ptr::write(
+                    ptr.field(rule.value_idx),
+                    self.forward(*ptr.field(rule.value_idx)),
+                );
+            }
+        }
+
+        reached
+    }
+
+    /// Traces the pending conditional fields whose keys have been reached since they were added.
+    ///
+    /// Returns `true` if more objects have been evacuated, which now need to be scanned.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle.
+    unsafe fn resolve_conditional_fields(&mut self) -> bool {
+        let next = self.next;
+        let mut pending = mem::take(&mut self.pending_conditional);
+        pending.retain(|&(ptr, rule)| !unsafe { self.trace_conditional(ptr, rule) });
+        self.pending_conditional.append(&mut pending);
+
+        self.next != next
+    }
+
+    /// Clears the key and the value of every pending conditional field, whose keys are now known to
+    /// be unreachable.
+    ///
+    /// # Safety
+    /// Must only be called at the end of a GC cycle, once the to-space has been fully scanned.
+    unsafe fn clear_unreached_conditional_fields(&mut self) {
+        for (ptr, rule) in mem::take(&mut self.pending_conditional) {
+            if let Some(recorder) = &mut self.snapshot
+                && let Some(&offset) = recorder.offsets.get(&ptr)
+            {
+                for idx in [rule.key_idx, rule.value_idx] {
+                    let at = offset + field_offset(idx).unwrap();
+                    let target = unsafe { *ptr.field(idx) };
+
+                    if let Some(positions) = recorder.pending.get_mut(&target) {
+                        positions.retain(|&pos| pos != at);
+                    }
+
+                    recorder.set_field(at, 0);
+                }
+            }
+
+            unsafe {
+                ptr::write(ptr.field(rule.key_idx), ObjPtr(ptr::null_mut()));
+                ptr::write(ptr.field(rule.value_idx), ObjPtr(ptr::null_mut()));
+            }
+        }
+    }
+
+    /// Pins the from-space objects that the words in [`Gc::stack_bounds`] point into, and forwards
+    /// their fields.
+    ///
+    /// Any word whose value lies within an object (including its header) counts as a reference to
+    /// it, regardless of whether it's actually a pointer.
+    ///
+    /// # Safety
+    /// Must be called at the start of a GC cycle, before anything is forwarded. `from_free` must be
+    /// the bounds of the free area of the from-space (the values [`Gc::next`] and [`Gc::limit`] had
+    /// before the spaces were flipped), and the stack range must be readable.
+    #[cfg(feature = "conservative")]
+    unsafe fn pin_stack_referents(&mut self, from_free: (*mut u8, *mut u8)) {
+        let (Some((low, high)), Some(from_space)) = (self.stack_bounds, &self.from_space) else {
+            return;
+        };
+
+        // objects allocated at the high end (during the previous cycle or by
+        // `Gc::alloc_transient`) can be referenced from the stack too.
+        let regions = [
+            (from_space.start, from_free.0),
+            (from_free.1, from_space.end()),
+        ];
+        let mut candidates = vec![];
+        let mut word = low.wrapping_byte_add(align_up(low.addr(), FIELD_SIZE) - low.addr());
+
+        while word < high {
+            let value = unsafe { word.read_volatile() };
+
+            if regions
+                .iter()
+                .any(|&(start, end)| (start.addr()..end.addr()).contains(&value))
+            {
+                candidates.push(value);
+            }
+
+            word = word.wrapping_add(1);
+        }
+
+        candidates.sort_unstable();
+        let mut candidates = candidates.into_iter().peekable();
+
+        for (start, end) in regions {
+            let mut addr = start;
+
+            while addr < end && candidates.peek().is_some() {
+                let ptr = ObjPtr(addr.cast());
+                let obj_end = unsafe { addr.byte_add(self.footprint(ptr)) };
+
+                while candidates
+                    .next_if(|&value| value < obj_end.addr())
+                    .is_some()
+                {
+                    if !unsafe { ptr.is_padding() } {
+                        self.pinned.insert(ptr);
+                    }
+                }
+
+                addr = obj_end;
+            }
+        }
+
+        // the pinned objects are never visited by the scan, so their fields are forwarded upfront.
+        for ptr in self.pinned.clone() {
+            unsafe { self.forward_obj_fields(ptr) };
+        }
+    }
+
+    /// Returns `true` if `ptr` is a from-space object pinned by a conservative root.
+    fn is_pinned(&self, ptr: ObjPtr) -> bool {
+        #[cfg(feature = "conservative")]
+        return !self.pinned.is_empty() && self.pinned.contains(&ptr);
+
+        #[cfg(not(feature = "conservative"))]
+        {
+            let _ = ptr;
+
+            false
+        }
+    }
+
+    /// Moves the pinned objects into the immortal space by turning the from-space into an immortal
+    /// chunk, with everything but the pinned objects overwritten with padding.
+    ///
+    /// Does nothing if there are no pinned objects.
+    ///
+    /// # Safety
+    /// Must only be called at the end of a GC cycle, once nothing refers to the unpinned objects in
+    /// the from-space.
+    #[cfg(feature = "conservative")]
+    unsafe fn retain_pinned(&mut self) {
+        if self.pinned.is_empty() {
+            return;
+        }
+
+        let from_space = self.from_space.take().unwrap();
+        let mut pinned = mem::take(&mut self.pinned).into_iter().collect::<Vec<_>>();
+        pinned.sort_unstable_by_key(|ptr| ptr.0);
+        let mut addr = from_space.start;
+
+        for ptr in pinned {
+            unsafe { fill_padding(addr, ptr.0.cast()) };
+            addr = unsafe { ptr.0.byte_add(ptr.size()).cast() };
+        }
+
+        unsafe { fill_padding(addr, from_space.end()) };
+        let size = from_space.size;
+
+        // keep bump-allocating in the last chunk.
+        let idx = self.immortal.chunks.len().saturating_sub(1);
+        self.immortal.chunks.insert(idx, (from_space, size));
+    }
+
+    /// Moves the finalizers of the from-space objects that haven't survived the GC cycle to
+    /// [`Gc::ready_finalizers`], and re-keys the rest by the objects' new addresses.
+    ///
+    /// # Safety
+    /// This method must only be called at the end of a GC cycle, once every live object has been
+    /// evacuated, while the from-space is still intact.
+    unsafe fn collect_finalizers(&mut self) {
+        let Some(from_space) = &self.from_space else {
+            return;
+        };
+
+        if self.finalizers.is_empty() {
+            return;
+        }
+
+        let is_dead = |ptr: ObjPtr| {
+            from_space.contains(ptr.0.cast())
+                && !self.is_pinned(ptr)
+                && !unsafe { self.is_forwarded(ptr) }
+        };
+
+        let mut finalizers = HashMap::with_capacity(self.finalizers.len());
+        let mut dying = vec![];
+
+        for (&addr, &finalizer) in &self.finalizers {
+            let ptr = ObjPtr(addr);
+
+            if is_dead(ptr) {
+                dying.push(ptr);
+            } else if from_space.contains(addr.cast()) && !self.is_pinned(ptr) {
+                finalizers.insert(unsafe { self.forwardee(ptr) }.0, finalizer);
+            } else {
+                finalizers.insert(addr, finalizer);
+            }
+        }
+
+        let order = unsafe { self.order_finalization(dying, is_dead) };
+        let ready = order
+            .into_iter()
+            .map(|ptr| self.finalizers[&ptr.0])
+            .collect::<Vec<_>>();
+
+        self.finalizers = finalizers;
+        self.ready_finalizers.extend(ready);
+    }
+
+    /// Orders the dying objects with finalizers so that referrers come before their referents, or
+    /// the other way around if [`Gc::finalize_referents_first`] is set.
+    ///
+    /// An object refers to another one if the latter is reachable from it via `Obj` fields, passing
+    /// only through other dead objects (as determined by `is_dead`). Cycles are broken at the object
+    /// with the lowest address, and a warning is printed.
+    ///
+    /// # Safety
+    /// The dead objects must still be intact.
+    unsafe fn order_finalization(
+        &self,
+        mut dying: Vec<ObjPtr>,
+        is_dead: impl Fn(ObjPtr) -> bool,
+    ) -> Vec<ObjPtr> {
+        dying.sort_unstable_by_key(|ptr| ptr.0.addr());
+        let index = dying
+            .iter()
+            .enumerate()
+            .map(|(idx, &ptr)| (ptr, idx))
+            .collect::<HashMap<_, _>>();
+
+        // edges[i] lists the finalizable objects the i-th one refers to directly, that is, without
+        // passing through another finalizable object.
+        let mut edges = vec![vec![]; dying.len()];
+        let mut in_degrees = vec![0usize; dying.len()];
+
+        for (from, &ptr) in dying.iter().enumerate() {
+            let mut visited = HashSet::from([ptr]);
+            let mut stack = vec![ptr];
+
+            while let Some(ptr) = stack.pop() {
+                let tag = unsafe { ptr.tag() };
+
+                for idx in 0..unsafe { ptr.field_count() } {
+                    let field = unsafe { *ptr.field(idx) };
+
+                    if tag.field_kind(idx) != StellaFieldKind::Obj
+                        || !is_dead(field)
+                        || !visited.insert(field)
+                    {
+                        continue;
+                    }
+
+                    match index.get(&field) {
+                        Some(&to) => {
+                            edges[from].push(to);
+                            in_degrees[to] += 1;
+                        }
+
+                        None => stack.push(field),
+                    }
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(dying.len());
+        let mut done = vec![false; dying.len()];
+
+        while order.len() < dying.len() {
+            let next = match (0..dying.len()).find(|&idx| !done[idx] && in_degrees[idx] == 0) {
+                Some(idx) => idx,
+
+                None => {
+                    let idx = (0..dying.len()).find(|&idx| !done[idx]).unwrap();
+                    eprintln!(
+                        "warning: the finalizers of {} objects form a cycle, finalizing {:?} first",
+                        dying.len() - order.len(),
+                        dying[idx].0,
+                    );
+
+                    idx
+                }
+            };
+
+            done[next] = true;
+            order.push(dying[next]);
+
+            for &to in &edges[next] {
+                in_degrees[to] -= 1;
+            }
+        }
+
+        if self.finalize_referents_first {
+            order.reverse();
+        }
+
+        order
+    }
+
+    /// Pushes `root` onto the root stack.
+    ///
+    /// If GC is in progress, the root is forwarded right away: the cycle may have already gone past
+    /// its position in the root stack.
+    ///
+    /// # Panics
+    /// Panics if `root` is null and [`Gc::strict_roots`] is set. Otherwise, a null slot is reported
+    /// and skipped, since it would crash the collector the next time it reads the roots.
+    ///
+    /// # Safety
+    /// Unless null, `root` must be valid for reads and writes. If GC is in progress, the slot must
+    /// hold a valid stella object, null, or a pointer outside of the from-space.
+    unsafe fn push_root(&mut self, root: *mut ObjPtr) {
+        if !root.is_null() {
+            self.roots.push(root);
+
+            if self.gc_in_progress {
+                unsafe { self.forward_root(root) };
+            }
+        } else if self.strict_roots {
+            panic!("pushing a null root slot");
+        } else {
+            eprintln!("warning: pushing a null root slot, ignoring");
+        }
+    }
+
+    /// Pops `root` off the root stack.
+    ///
+    /// Unless [`Gc::strict_roots`] is set, a null `root` is ignored (its push has been ignored as
+    /// well), and so is popping from an empty stack, which is reported instead.
+    ///
+    /// # Panics
+    /// Panics if the root stack is empty and [`Gc::strict_roots`] is set.
+    fn pop_root(&mut self, root: *mut ObjPtr) {
+        // the matching push has been ignored.
+        if root.is_null() && !self.strict_roots {
+            return;
+        }
+
+        match self.roots.pop() {
+            Some(popped) => debug_assert_eq!(root, popped),
+            None if self.strict_roots => panic!("popping from empty root stack"),
+            None => eprintln!("warning: popping {root:?} from empty root stack, ignoring"),
+        }
+    }
+
+    /// Releases the memory the root stack has retained beyond its current size, unless GC is in
+    /// progress.
+    fn trim_roots(&mut self) {
+        if !self.gc_in_progress {
+            self.roots.shrink_to_fit();
+        }
+    }
+
+    /// Returns the current referents of the roots, including the value roots.
+    fn root_referents(&self) -> Vec<ObjPtr> {
+        self.roots
+            .iter()
+            .map(|&root| unsafe { *root })
+            .chain(self.value_roots.iter().flatten().copied())
+            .collect()
+    }
+
+    /// Forwards the referents of the roots.
+    ///
+    /// Returns `true` if more objects have been evacuated, which now need to be scanned.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. The roots must point to valid stella
+    /// objects.
+    unsafe fn forward_roots(&mut self) -> bool {
+        let next = self.next;
+        let roots = mem::take(&mut self.roots);
+
+        for &root in &roots {
+            unsafe { self.forward_root(root) };
+        }
+
+        self.root_cursor = roots.len();
+        self.roots = roots;
+        unsafe { self.forward_value_roots() };
+
+        self.next != next
+    }
+
+    /// Forwards the referent of a root, unless it has been found to be illegal (see
+    /// [`Gc::illegal_roots`]).
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. The root must point to a valid stella
+    /// object unless it's illegal.
+    unsafe fn forward_root(&mut self, root: *mut ObjPtr) {
+        #[cfg(feature = "checked")]
+        if self
+            .illegal_roots
+            .get(&root)
+            .is_some_and(|&value| value == unsafe { *root })
+        {
+            return;
+        }
+
+        unsafe { ptr::write(root, self.forward(*root)) };
+    }
+
+    /// Records the roots that point to a managed space but not to a valid object in
+    /// [`Gc::illegal_roots`], reporting each of them.
+    ///
+    /// Roots pointing to unmanaged memory are assumed to refer to static objects and are fine.
+    #[cfg(feature = "checked")]
+    fn find_illegal_roots(&mut self) {
+        self.illegal_roots.clear();
+
+        for &root in &self.roots {
+            let value = unsafe { *root };
+
+            if self.classify_space(value.0) == SpaceClass::Unmanaged {
+                continue;
+            }
+
+            if let Err(defect) = self.validate_object(value) {
+                eprintln!(
+                    "warning: root {root:?} points to {:?}, which is not a valid object ({defect}); \
+                    leaving it as is",
+                    value.0,
+                );
+                self.illegal_roots.insert(root, value);
+            }
+        }
+    }
+
+    /// Forwards the referents of the value roots.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. The roots must point to valid stella
+    /// objects.
+    unsafe fn forward_value_roots(&mut self) {
+        for idx in 0..self.value_roots.len() {
+            if let Some(obj) = self.value_roots[idx] {
+                self.value_roots[idx] = Some(unsafe { self.forward(obj) });
+            }
+        }
+    }
+
+    /// Forwards the referents of the next [`Gc::root_slice`] roots in the root stack starting at
+    /// [`Gc::root_cursor`], or of all the remaining ones if the slice size is 0.
+    ///
+    /// The roots popped since the cursor was last moved are skipped, and the ones pushed in their
+    /// place are left to the final pass over the roots at the end of the cycle.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. The roots must point to valid stella
+    /// objects.
+    unsafe fn forward_root_slice(&mut self) {
+        let len = self.roots.len();
+        let start = self.root_cursor.min(len);
+        let end = match self.root_slice {
+            0 => len,
+            slice => start.saturating_add(slice).min(len),
+        };
+
+        for idx in start..end {
+            unsafe { self.forward_root(self.roots[idx]) };
+        }
+
+        self.root_cursor = end;
+    }
+
+    /// Forwards the from-space pointers the mutator may have stored outside of the scanned area in
+    /// the Brooks mode: in the roots, the objects allocated during the cycle, and the immortal
+    /// objects.
+    ///
+    /// Returns `true` if more objects have been evacuated, which now need to be scanned.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle, and every object allocated during the
+    /// cycle must have been initialized.
+    unsafe fn forward_mutator_refs(&mut self) -> bool {
+        let next = self.next;
+        unsafe { self.forward_roots() };
+
+        let mut addr = self.limit;
+
+        while addr < self.to_space.end() {
+            let ptr = ObjPtr(addr.cast());
+            let tag = unsafe { ptr.try_tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.is_none_or(|tag| tag.field_kind(idx) != StellaFieldKind::Obj) {
+                    continue;
+                }
+
+                let field_ptr = unsafe { ptr.field(idx) };
+
+                unsafe { ptr::write(field_ptr, self.forward(*field_ptr)) };
+            }
+
+            addr = unsafe { addr.byte_add(self.footprint(ptr)) };
+        }
+
+        unsafe { self.forward_immortal_fields() };
+
+        self.next != next
+    }
+
+    /// Continues the current GC cycle after the mutator has allocated `allocated` bytes.
+    ///
+    /// Every allocated byte adds [`Stats::scan_ratio`] bytes to the scan debt, which is paid off in
+    /// slices of at most [`MAX_SCAN_SLICE`] bytes, so that a single large allocation doesn't scan
+    /// the whole heap at once. If the remaining free memory is less than the debt plus the objects
+    /// still waiting to be scanned, the cycle is finished right away instead: otherwise, a low scan
+    /// ratio could let the mutator use up the free memory before the cycle ends, and no garbage
+    /// could be reclaimed until it does. The survivors still have to fit in the to-space, though.
+    ///
+    /// Since objects are scanned whole, a slice may overshoot. The excess isn't credited towards
+    /// later allocations, which keeps the collector slightly ahead of the mutator.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle.
+    unsafe fn pace_gc(&mut self, allocated: usize) {
+        let allocated = (allocated as f64 * self.stats.scan_ratio).ceil() as usize;
+        self.scan_debt = self.scan_debt.saturating_add(allocated);
+        let debt = self.scan_debt;
+
+        if self.cycle_pins > 0 {
+            return;
+        }
+
+        let unscanned = unsafe { self.next.byte_offset_from_unsigned(self.scan) };
+
+        if self.free_memory() < unscanned.saturating_add(debt) {
+            while self.gc_in_progress {
+                unsafe { self.run_gc(self.to_space.size) };
+            }
+
+            self.scan_debt = 0;
+
+            return;
+        }
+
+        let scan = self.scan;
+        unsafe { self.run_gc(debt.min(MAX_SCAN_SLICE)) };
+
+        let scanned = unsafe { self.scan.byte_offset_from_unsigned(scan) };
+        self.scan_debt = self.scan_debt.saturating_sub(scanned);
+    }
+
+    /// Finishes the GC cycle in progress, if any.
+    ///
+    /// # Panics
+    /// Panics if collection is suppressed (see [`Gc::cycle_pins`]).
+    ///
+    /// # Safety
+    /// All roots must have already been registered in the root stack.
+    unsafe fn finish_gc(&mut self) {
+        assert!(
+            self.cycle_pins == 0,
+            "cannot collect garbage while collection is suppressed by gc_pin_cycle",
+        );
+
+        while self.gc_in_progress {
+            unsafe { self.run_gc(self.to_space.size) };
+        }
+    }
+
+    /// Finishes the GC cycle in progress, if any, and then runs a full cycle to completion.
+    ///
+    /// Returns the number of bytes that survived the full cycle and the number of bytes it
+    /// reclaimed (the memory used in the to-space before the cycle minus the survivors).
+    ///
+    /// # Panics
+    /// Panics if collection is suppressed (see [`Gc::cycle_pins`]) or the new to-space can't be
+    /// allocated.
+    ///
+    /// # Safety
+    /// All roots must have already been registered in the root stack.
+    unsafe fn collect(&mut self) -> (usize, usize) {
+        unsafe { self.finish_gc() };
+
+        let used = self.to_space_used_memory();
+        assert!(
+            unsafe { self.begin_gc() },
+            "out of memory: cannot allocate the to-space",
+        );
+
+        while self.gc_in_progress {
+            unsafe { self.run_gc(self.to_space.size) };
+        }
+
+        let survived = self.to_space_used_memory();
+
+        (survived, used.saturating_sub(survived))
+    }
+
+    /// Continues the current GC cycle by scanning `n` bytes.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle.
+    unsafe fn run_gc(&mut self, n: usize) {
+        self.tick_watchdog();
+
+        if self.root_cursor < self.roots.len() {
+            unsafe { self.forward_root_slice() };
+        }
+
+        let target = self.scan.wrapping_byte_add(n);
+
+        loop {
+            while self.scan < self.next {
+                if self.scan > target {
+                    self.check_pointers();
+
+                    return;
+                }
+
+                self.tick_watchdog();
+                let ptr = ObjPtr(self.scan.cast());
+                unsafe { self.forward_obj_fields(ptr) };
+                self.scan = unsafe { self.scan.byte_add(self.footprint(ptr)) };
+
+                // overshooting would end the cycle with the objects in between left unscanned.
+                if self.scan > self.next {
+                    eprintln!("{}", unsafe { self.display_obj(ptr, false) });
+                    panic!(
+                        "scanning {:?} overshot the end of the grey area by {} B",
+                        ptr.0,
+                        unsafe { self.scan.byte_offset_from_unsigned(self.next) },
+                    );
+                }
+            }
+
+            if unsafe { self.resolve_conditional_fields() } {
+                continue;
+            }
+
+            // the roots pushed during the cycle may still point to the from-space. the read barrier
+            // keeps any other from-space pointers away from the mutator in the Baker mode, unless
+            // it could read them from the roots that hadn't been forwarded yet.
+            let evacuated = match self.barrier_mode {
+                BarrierMode::Baker | BarrierMode::Snapshot if !self.roots_deferred => unsafe {
+                    self.forward_roots()
+                },
+
+                _ => unsafe { self.forward_mutator_refs() },
+            };
+
+            if !evacuated {
+                break;
+            }
+        }
+
+        unsafe { self.clear_unreached_conditional_fields() };
+
+        // the values have already been evacuated, so this only replaces them with their copies.
+        for (ptr, idx) in mem::take(&mut self.dirty_fields) {
+            unsafe { ptr::write(ptr.field(idx), self.forward(*ptr.field(idx))) };
+        }
+
+        #[cfg(feature = "verify-after-gc")]
+        unsafe {
+            self.verify_no_from_space_refs()
+        };
+
+        // the from-space must still be intact to find out which objects have died.
+        unsafe { self.collect_finalizers() };
+
+        #[cfg(feature = "conservative")]
+        unsafe {
+            self.retain_pinned()
+        };
+
+        // the entries of the objects that didn't survive still refer to the from-space.
+        let to_space = &self.to_space;
+        let immortal = &self.immortal;
+        self.obj_info
+            .retain(|&addr, _| to_space.contains(addr.cast()) || immortal.contains(addr.cast()));
+
+        #[cfg(feature = "poison")]
+        if self.poison_from_space
+            && let Some(from_space) = &self.from_space
+        {
+            unsafe { ptr::write_bytes(from_space.start, POISON_BYTE, from_space.size) };
+        }
+
+        self.gc_in_progress = false;
+        self.roots_deferred = false;
+        self.clobbered_forwarding.clear();
+
+        #[cfg(feature = "checked")]
+        self.illegal_roots.clear();
+
+        #[cfg(feature = "testing")]
+        if self.from_space.as_ref().is_some_and(|space| space.borrowed) {
+            self.spare_space = self.from_space.take();
+        }
+
+        #[cfg(all(test, feature = "poison"))]
+        if let Some(from_space) = &self.from_space {
+            let bytes = unsafe { slice::from_raw_parts(from_space.start, from_space.size) };
+            tests::RETIRED_FROM_SPACE.set(bytes.to_vec());
+        }
+
+        self.from_space = None;
+        self.minor_floor = self.next;
+        publish_from_space(None);
+
+        if self.verify_after_collect {
+            self.verify_heap();
+        }
+
+        let survived = unsafe { self.next.byte_offset_from_unsigned(self.to_space.start) };
+        self.last_survived = Some(survived);
+        let ratio = survived as f64 / self.cycle_used.max(1) as f64;
+        self.survival_ratio = Some(self.survival_ratio.map_or(ratio, |prev| {
+            SURVIVAL_EMA_WEIGHT * ratio + (1.0 - SURVIVAL_EMA_WEIGHT) * prev
+        }));
+        self.log_event(|gc| GcEvent::CycleEnd {
+            cycle: gc.stats.gc_cycles,
+            survived,
+        });
+
+        if self.cycle_logging
+            && let Some((started_at, roots, evacuated_objs)) = self.cycle_start.take()
+        {
+            eprintln!(
+                "GC cycle #{}: {roots} roots, {} objects evacuated, {survived} B survived, took {:?}",
+                self.stats.gc_cycles,
+                self.stats.evacuated_objs - evacuated_objs,
+                started_at.elapsed(),
+            );
+        }
+
+        if self.to_space.size < self.min_heap && survived > self.to_space.size / 2 {
+            self.space_size = self.space_size.max(self.min_heap);
+        }
+
+        self.reserve_survivor_headroom();
+
+        if self.post_gc_callback.is_some() {
+            self.pending_post_gc.push((
+                self.to_space_used_memory(),
+                self.to_space.size,
+                survived,
+                self.stats.gc_cycles,
+            ));
+        }
+
+        self.check_pointers();
+    }
+
+    /// Checks that the to-space pointers are ordered correctly, panicking otherwise.
+    ///
+    /// `next` must never pass `limit`, both must stay within the to-space, and, during a cycle,
+    /// `scan` must lie between the start of the to-space and `next`. A violation means some
+    /// allocation or evacuation has overwritten memory it didn't own, so it's better to stop right
+    /// away than to keep running with a corrupted heap.
+    ///
+    /// The check is only performed in debug builds.
+    fn check_pointers(&self) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let start = self.to_space.start;
+        let end = self.to_space.end();
+        let mut valid = start <= self.next && self.next <= self.limit && self.limit <= end;
+
+        if self.gc_in_progress {
+            valid &= start <= self.scan && self.scan <= self.next;
+        }
+
+        if valid {
+            return;
+        }
+
+        let offset = |ptr: *mut u8| ptr.addr().wrapping_sub(start.addr()) as isize;
+
+        panic!(
+            "the to-space pointers are out of order{}: scan = {:?} (to{:+}), next = {:?} (to{:+}), limit = {:?} (to{:+}), to-space = {:?}..{:?} ({} B)",
+            if self.gc_in_progress {
+                " during a GC cycle"
+            } else {
+                ""
+            },
+            self.scan,
+            offset(self.scan),
+            self.next,
+            offset(self.next),
+            self.limit,
+            offset(self.limit),
+            start,
+            end,
+            self.to_space.size,
+        );
+    }
+
+    /// Grows the semi-spaces if the memory left free by the last GC cycle is less than
+    /// [`Gc::survivor_headroom`].
+    ///
+    /// The new size is chosen so that the memory used right now would leave exactly the requested
+    /// share of the space free. It takes effect once the next cycle allocates its to-space (or
+    /// right away if the to-space can be grown in place).
+    fn reserve_survivor_headroom(&mut self) {
+        let headroom = usize::from(self.survivor_headroom);
+
+        if headroom == 0 {
+            return;
+        }
+
+        let used = self.to_space_used_memory();
+        let free = self.to_space.size - used;
+
+        if free.saturating_mul(100) >= self.to_space.size.saturating_mul(headroom) {
+            return;
+        }
+
+        let wanted = used.saturating_mul(100).div_ceil(100 - headroom);
+        let wanted =
+            checked_align_up(wanted, ALIGNMENT).unwrap_or(align_down(usize::MAX, ALIGNMENT));
+        self.space_size = self.space_size.max(wanted);
+    }
+
+    /// Counts a step of the current GC cycle, panicking if the cycle has taken too many of them.
+    ///
+    /// Every increment of a cycle scans at least one object or finishes the cycle, so a cycle that
+    /// takes more steps than there is room for objects in the to-space (plus one per root for the
+    /// root slices) must be stuck, e.g., because an object with a zero size keeps the scan pointer
+    /// in place. Without the check, such a cycle would never end, and no memory would ever be
+    /// reclaimed again.
+    fn tick_watchdog(&mut self) {
+        self.cycle_steps += 1;
+
+        if self.cycle_steps
+            <= self
+                .cycle_in_progress_deadline
+                .saturating_add(self.roots.len())
+        {
+            return;
+        }
+
+        let offset = |ptr: *mut u8| ptr.addr().wrapping_sub(self.to_space.start.addr());
+
+        if self.scan < self.next {
+            eprintln!("{}", unsafe {
+                self.display_obj(ObjPtr(self.scan.cast()), false)
+            });
+        }
+
+        panic!(
+            "GC cycle #{} is stuck: {} steps taken, scan = +{}, next = +{}, limit = +{}, to-space size = {} B",
+            self.stats.gc_cycles,
+            self.cycle_steps,
+            offset(self.scan),
+            offset(self.next),
+            offset(self.limit),
+            self.to_space.size,
+        );
+    }
+
+    /// Forwards a pointer from the from-space to the to-space if necessary.
+    ///
+    /// Returns a pointer to the forwarded object, or `ptr` if forwarding is not applicable.
+    ///
+    /// # Safety
+    /// If `ptr` points to the from-space, it must point to the start of a valid stella object. The
+    /// same requirement applies transitively to the contents of its fields.
+    unsafe fn forward(&mut self, ptr: ObjPtr) -> ObjPtr {
+        if self
+            .from_space
+            .as_ref()
+            .is_some_and(|from_space| from_space.contains(ptr.0.cast()))
+        {
+            if self.is_pinned(ptr) {
+                return ptr;
+            }
+
+            let mut result = unsafe { self.forwardee(ptr) };
+
+            // only refs can be mutated to point to themselves: anything else is created before any
+            // references to it exist.
+            #[cfg(feature = "checked")]
+            assert!(
+                self.barrier_mode == BarrierMode::Brooks
+                    || result != ptr
+                    || matches!(unsafe { ptr.try_tag() }, Some(StellaTag::Ref)),
+                "self-referential forwarding pointer at {:?}",
+                ptr.0,
+            );
+
+            if !self.to_space.contains(result.0.cast()) {
+                unsafe { self.chase(ptr) };
+                result = unsafe { self.forwardee(ptr) };
+            }
+
+            assert!(self.to_space.contains(result.0.cast()));
+
+            result
+        } else {
+            ptr
+        }
+    }
+
+    /// Performs a semi-DFS walk forwarding pointers, starting with `ptr`.
+    ///
+    /// In the breadth-first scan order, only `ptr` itself is forwarded.
+    ///
+    /// # Safety
+    /// `ptr` must point to the start of a valid stella object in the from-space. The same
+    /// requirement applies transitively to the contents of its fields.
+    unsafe fn chase(&mut self, mut ptr: ObjPtr) {
+        loop {
+            let info = if self.obj_info.is_empty() {
+                None
+            } else {
+                self.obj_info.remove(&ptr.0)
+            };
+
+            let align = info.unwrap_or_default().align;
+            let start = self
+                .next
+                .wrapping_byte_add(align_up(self.next.addr(), align) - self.next.addr());
+
+            let wr = ObjPtr(start.cast());
+            let end = start.wrapping_byte_add(unsafe { self.footprint(ptr) });
+
+            if end > self.limit || end < self.next {
+                panic!("out of memory");
+            }
+
+            unsafe { fill_padding(self.next, start) };
+            self.next = end;
+            // the cycle log reports the object count, so it's always tracked.
+            self.stats.evacuated_objs += 1;
+            if stats_enabled() {
+                self.stats.evacuated_bytes += unsafe { end.byte_offset_from_unsigned(start) };
+            }
+
+            if let Some(info) = info {
+                self.obj_info.insert(wr.0, info);
+            }
+
+            self.log_event(|gc| GcEvent::Forward {
+                from: gc.classify_space(ptr.0),
+                to: gc.classify_space(wr.0),
+            });
+
+            let mut next = ObjPtr(ptr::null_mut());
+            let rule = unsafe { self.conditional_field(ptr) };
+            let tag = unsafe { ptr.try_tag() };
+            let field_count = unsafe { ptr.field_count() };
+            unsafe { ptr::copy(ptr.0, wr.0, 1) };
+
+            if field_count == 0 {
+                unsafe { Self::clear_forwarding_slot(wr) };
+            }
+
+            for idx in 0..field_count {
+                let field = unsafe { *ptr.field(idx) };
+                unsafe { ptr::write(wr.field(idx), field) };
+
+                // a ref may point to itself, in which case it's about to be forwarded anyway.
+                if self.scan_order == ScanOrder::DepthFirst
+                    && field != ptr
+                    && tag.is_some_and(|tag| tag.field_kind(idx) == StellaFieldKind::Obj)
+                    && !rule.is_some_and(|rule| rule.covers(idx))
+                    && self
+                        .from_space
+                        .as_ref()
+                        .is_some_and(|from_space| from_space.contains(field.0.cast()))
+                    && !self.is_pinned(field)
+                    && !self
+                        .to_space
+                        .contains(unsafe { self.forwardee(field) }.0.cast())
+                {
+                    next = field;
+                }
+            }
+
+            match self.barrier_mode {
+                BarrierMode::Baker | BarrierMode::Snapshot => unsafe {
+                    ptr::write(ptr.field(0), wr)
+                },
+
+                BarrierMode::Brooks => unsafe {
+                    ptr::write(Self::forwarding_word(wr), wr);
+                    ptr::write(Self::forwarding_word(ptr), wr);
+                },
+            }
+
+            unsafe { self.record_evacuated(ptr, wr) };
+            ptr = next;
+
+            if ptr.0.is_null() {
+                break;
+            }
+        }
+    }
+
+    /// Adds an object that has just been evacuated to [`Gc::snapshot`] if it's being recorded.
+    ///
+    /// The `Obj` fields referring to the objects recorded earlier are written as their offsets
+    /// right away. The rest are patched as their referents get recorded.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. `ptr` must point to a from-space object
+    /// that has just been forwarded to `wr`, whose fields haven't been scanned yet.
+    unsafe fn record_evacuated(&mut self, ptr: ObjPtr, wr: ObjPtr) {
+        let Some(mut recorder) = self.snapshot.take() else {
+            return;
+        };
+
+        let offset = unsafe { recorder.push(wr) };
+        recorder.offsets.insert(wr, offset);
+
+        for at in recorder.pending.remove(&ptr).unwrap_or_default() {
+            recorder.set_field(at, offset | 1);
+        }
+
+        let tag = unsafe { wr.try_tag() };
+
+        for idx in 0..unsafe { wr.field_count() } {
+            if tag.is_none_or(|tag| tag.field_kind(idx) != StellaFieldKind::Obj) {
+                continue;
+            }
+
+            let at = offset + field_offset(idx).unwrap();
+            let field = unsafe { *wr.field(idx) };
+
+            if !self
+                .from_space
+                .as_ref()
+                .is_some_and(|from_space| from_space.contains(field.0.cast()))
+            {
+                if let Some(&target) = recorder.offsets.get(&field) {
+                    recorder.set_field(at, target | 1);
+                } else if !field.0.is_null() {
+                    recorder.outside.push((at, field));
+                }
+
+                continue;
+            }
+
+            let forwardee = unsafe { self.forwardee(field) };
+
+            if !self.is_pinned(field) && self.to_space.contains(forwardee.0.cast()) {
+                recorder.set_field(at, recorder.offsets[&forwardee] | 1);
+            } else {
+                recorder.pending.entry(field).or_default().push(at);
+            }
+        }
+
+        self.snapshot = Some(recorder);
+    }
+
+    /// Reads the value of a field of a stella object, forwarding it if necessary.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object. `field_idx` must be less than the field count.
+    unsafe fn read_barrier(&mut self, ptr: ObjPtr, field_idx: usize) -> ObjPtr {
+        if stats_enabled() {
+            READS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if self.barrier_mode == BarrierMode::Brooks {
+            let obj = unsafe { self.resolve(ptr) };
+
+            if obj != ptr {
+                if stats_enabled() {
+                    self.stats.read_barriers += 1;
+                }
+
+                self.log_event(|gc| GcEvent::ReadBarrier {
+                    obj: gc.classify_space(ptr.0),
+                    field_idx,
+                });
+            }
+
+            return unsafe { *obj.field(field_idx) };
+        }
+
+        // a root that hasn't been forwarded yet may have handed the mutator a from-space pointer.
+        let ptr = if self.gc_in_progress
+            && self
+                .from_space
+                .as_ref()
+                .is_some_and(|from_space| from_space.contains(ptr.0.cast()))
+        {
+            unsafe { self.forward(ptr) }
+        } else {
+            ptr
+        };
+
+        let mut result = unsafe { *ptr.field(field_idx) };
+
+        // raw payloads may alias the from-space by coincidence; only real references get forwarded.
+        // the fields of an object with an unknown tag aren't known to be references either.
+        if self.gc_in_progress
+            && unsafe { ptr.try_tag() }
+                .is_some_and(|tag| tag.field_kind(field_idx) == StellaFieldKind::Obj)
+            && self
+                .from_space
+                .as_ref()
+                .is_some_and(|from_space| from_space.contains(result.0.cast()))
+        {
+            unsafe {
+                result = self.forward(result);
+                ptr::write(ptr.field(field_idx), result);
+            }
+
+            if stats_enabled() {
+                self.stats.read_barriers += 1;
+            }
+
+            self.log_event(|gc| GcEvent::ReadBarrier {
+                obj: gc.classify_space(ptr.0),
+                field_idx,
+            });
+
+            if self.eager_forward {
+                unsafe { self.forward_fields(ptr) };
+            }
+        }
+
+        result
+    }
+
+    /// Forwards every `Obj` field of the object that points to the from-space, except for the ones
+    /// covered by a conditional field rule. The fields of an object with an unknown tag are left
+    /// alone.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn forward_fields(&mut self, ptr: ObjPtr) {
+        let tag = unsafe { ptr.try_tag() };
+        let rule = unsafe { self.conditional_field(ptr) };
+
+        for idx in 0..unsafe { ptr.field_count() } {
+            let field_ptr = unsafe { ptr.field(idx) };
+            let field = unsafe { *field_ptr };
+
+            if tag.is_some_and(|tag| tag.field_kind(idx) == StellaFieldKind::Obj)
+                && !rule.is_some_and(|rule| rule.covers(idx))
+                && self
+                    .from_space
+                    .as_ref()
+                    .is_some_and(|from_space| from_space.contains(field.0.cast()))
+            {
+                unsafe { ptr::write(field_ptr, self.forward(field)) };
+                if stats_enabled() {
+                    self.stats.eager_forwards += 1;
+                }
+            }
+        }
+    }
+
+    /// Records a write of `value` to a field of a GC-managed object.
+    ///
+    /// The write itself is performed by the caller afterwards.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object, and `value` must be a valid value of the field.
+    unsafe fn record_write(&mut self, ptr: ObjPtr, field_idx: usize, value: ObjPtr) {
+        let obj = match self.barrier_mode {
+            BarrierMode::Brooks => unsafe { self.resolve(ptr) },
+
+            // the pointer may come from a root that hasn't been forwarded yet.
+            BarrierMode::Baker | BarrierMode::Snapshot => unsafe { self.forward(ptr) },
+        };
+
+        if self.barrier_mode == BarrierMode::Snapshot && self.gc_in_progress {
+            unsafe { self.snapshot_old_value(obj, field_idx) };
+        }
+
+        // the caller only updates the stale from-space copy.
+        if obj != ptr {
+            unsafe { ptr::write(obj.field(field_idx), value) };
+
+            if field_idx == 0 && self.barrier_mode != BarrierMode::Brooks {
+                self.clobbered_forwarding.insert(ptr, obj);
+            }
+        }
+
+        let ptr = obj;
+
+        match self.classify_space(ptr.0) {
+            SpaceClass::From { .. } | SpaceClass::To { .. } | SpaceClass::Immortal => {
+                if stats_enabled() {
+                    self.stats.writes += 1;
+                }
+
+                if !self.gc_in_progress && (self.minor_floor..self.next).contains(&value.0.cast()) {
+                    self.remembered.insert((ptr, field_idx));
+                }
+            }
+
+            SpaceClass::Unmanaged => {}
+        }
+
+        let tag = unsafe { ptr.try_tag() };
+
+        if self.gc_in_progress
+            && (self.roots_deferred || field_idx == 0 && matches!(tag, Some(StellaTag::Ref)))
+            && tag.is_some_and(|tag| tag.field_kind(field_idx) == StellaFieldKind::Obj)
+            && self.is_scanned(ptr)
+            && self
+                .from_space
+                .as_ref()
+                .is_some_and(|from_space| from_space.contains(value.0.cast()))
+        {
+            // the scan won't visit the object again, so the value must be evacuated right away.
+            unsafe { self.forward(value) };
+            self.dirty_fields.push((ptr, field_idx));
+        }
+    }
+
+    /// Evacuates the from-space object a field is about to stop referring to, so that it survives
+    /// the current GC cycle as required by [`BarrierMode::Snapshot`].
+    ///
+    /// Does nothing if the field isn't an `Obj` field or its value has already been evacuated.
+    ///
+    /// # Safety
+    /// This method must only be called during a GC cycle. `ptr` must point to a valid stella object,
+    /// and `field_idx` must be less than its field count.
+    unsafe fn snapshot_old_value(&mut self, ptr: ObjPtr, field_idx: usize) {
+        if unsafe { ptr.try_tag() }
+            .is_none_or(|tag| tag.field_kind(field_idx) != StellaFieldKind::Obj)
+        {
+            return;
+        }
+
+        let old = unsafe { *ptr.field(field_idx) };
+
+        if self
+            .from_space
+            .as_ref()
+            .is_some_and(|from_space| from_space.contains(old.0.cast()))
+            && !self.is_pinned(old)
+            && !unsafe { self.is_forwarded(old) }
+        {
+            unsafe { self.forward(old) };
+            if stats_enabled() {
+                self.stats.write_barriers += 1;
+            }
+        }
+    }
+
+    /// Stores `value` in a field of an object, running the write barrier first.
+    ///
+    /// # Panics
+    /// Panics if `field_idx` is out of bounds.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object, and `value` must be a valid value of the field.
+    unsafe fn set_field(&mut self, ptr: ObjPtr, field_idx: usize, value: ObjPtr) {
+        let field_count = unsafe { ptr.field_count() };
+        assert!(
+            field_idx < field_count,
+            "field index {field_idx} is out of bounds for an object with {field_count} fields",
+        );
+
+        unsafe {
+            self.record_write(ptr, field_idx, value);
+            ptr::write(ptr.field(field_idx), value);
+        }
+    }
+
+    /// Same as [`Gc::set_field`], but first checks that `value` is legal for the field when the
+    /// `checked-writes` feature is enabled.
+    ///
+    /// An `Obj` field may only hold null, one of the runtime's static objects, or an object that
+    /// passes [`Gc::validate_object`].
+    ///
+    /// # Panics
+    /// Panics if `field_idx` is out of bounds, or if the check fails, after printing the offending
+    /// value if it's managed.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn set_field_checked(&mut self, ptr: ObjPtr, field_idx: usize, value: ObjPtr) {
+        #[cfg(feature = "checked-writes")]
+        if unsafe { ptr.try_tag() }
+            .is_some_and(|tag| tag.field_kind(field_idx) == StellaFieldKind::Obj)
+            && !value.0.is_null()
+            && !is_static_obj(value)
+            && let Err(defect) = self.validate_object(value)
+        {
+            if defect != ObjDefect::Unmanaged {
+                eprintln!("{}", unsafe { self.display_obj(value, false) });
+            }
+
+            panic!(
+                "storing {:?} into field #{field_idx} of {:?}: {defect}",
+                value.0, ptr.0,
+            );
+        }
+
+        unsafe { self.set_field(ptr, field_idx, value) };
+    }
+
+    /// Returns the number of fields of a tuple.
+    ///
+    /// Returns `None` and records the reason in [`Gc::last_error`] if `ptr` isn't a tuple.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn tuple_arity(&mut self, ptr: ObjPtr) -> Option<usize> {
+        match unsafe { ptr.try_tag() } {
+            Some(StellaTag::Tuple) => Some(unsafe { ptr.field_count() }),
+
+            tag => {
+                self.set_last_error(format_args!(
+                    "{:?} is not a tuple (its tag is {})",
+                    ptr.0,
+                    tag.map_or_else(|| "unknown".to_owned(), |tag| tag.to_string()),
+                ));
+
+                None
+            }
+        }
+    }
+
+    /// Reads a field of a tuple through the read barrier.
+    ///
+    /// Returns `None` and records the reason in [`Gc::last_error`] if `ptr` isn't a tuple or
+    /// `field_idx` is out of bounds.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn tuple_field(&mut self, ptr: ObjPtr, field_idx: usize) -> Option<ObjPtr> {
+        let arity = unsafe { self.tuple_arity(ptr) }?;
+
+        if field_idx >= arity {
+            self.set_last_error(format_args!(
+                "field index {field_idx} is out of bounds for a tuple with {arity} fields",
+            ));
+
+            return None;
+        }
+
+        Some(unsafe { self.read_barrier(ptr, field_idx) })
+    }
+
+    /// Returns the elements of a tuple, read through the read barrier, with nested tuples
+    /// recursively replaced by their elements.
+    ///
+    /// For instance, `((a, b), c, ())` yields `[a, b, c]`. A non-tuple `ptr` is treated as a tuple
+    /// with a single element.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object. The nested tuples must not form a cycle.
+    unsafe fn flatten_tuple(&mut self, ptr: ObjPtr) -> Vec<ObjPtr> {
+        if !matches!(unsafe { ptr.try_tag() }, Some(StellaTag::Tuple)) {
+            return vec![ptr];
+        }
+
+        let mut result = vec![];
+        let mut stack = vec![(ptr, 0)];
+
+        while let Some((tuple, idx)) = stack.pop() {
+            if idx == unsafe { tuple.field_count() } {
+                continue;
+            }
+
+            stack.push((tuple, idx + 1));
+            let elem = unsafe { self.read_barrier(tuple, idx) };
+
+            if matches!(unsafe { elem.try_tag() }, Some(StellaTag::Tuple)) {
+                stack.push((elem, 0));
+            } else {
+                result.push(elem);
+            }
+        }
+
+        result
+    }
+
+    /// Returns `true` if the object won't be scanned again in the current GC cycle.
+    fn is_scanned(&self, ptr: ObjPtr) -> bool {
+        match self.classify_space(ptr.0) {
+            SpaceClass::To { .. } => !(self.scan..self.next).contains(&ptr.0.cast()),
+            SpaceClass::Immortal => true,
+            SpaceClass::From { .. } => self.is_pinned(ptr),
+            SpaceClass::Unmanaged => false,
+        }
+    }
+
+    /// Reclaims the unreachable objects allocated at or after `since` bytes from the start of the
+    /// to-space, leaving older objects untouched.
+    ///
+    /// The collection set is the tail of the to-space starting at the first object located at
+    /// `since` or later, but never before [`Gc::minor_floor`]. Its live objects are slid down to
+    /// the start of the set, preserving their order, after which they're considered old.
+    ///
+    /// Liveness is approximated. An object in the collection set is considered alive if it's
+    /// reachable, via other objects in the set, from:
+    /// - the roots,
+    /// - the fields of immortal objects,
+    /// - the fields of the objects above [`Gc::limit`] (see [`Gc::alloc_transient`]),
+    /// - the fields recorded in [`Gc::remembered`].
+    ///
+    /// Every object outside the set is thus assumed to be alive. This relies on older objects only
+    /// ever getting pointers to younger ones via the write barrier: initializing stores are fine,
+    /// since an object can only be initialized with pointers to objects that already exist.
+    ///
+    /// Does nothing if a GC cycle is in progress or collection is suppressed (see
+    /// [`Gc::cycle_pins`]).
+    ///
+    /// # Safety
+    /// All roots must have already been registered in the root stack.
+    unsafe fn minor_collect(&mut self, since: usize) {
+        if self.gc_in_progress || self.cycle_pins > 0 {
+            return;
+        }
+
+        let target = self
+            .to_space
+            .start
+            .wrapping_byte_add(since.min(self.to_space.size));
+        let mut start = self.minor_floor;
+
+        while start < target && start < self.next {
+            start = unsafe { start.byte_add(self.footprint(ObjPtr(start.cast()))) };
+        }
+
+        if start >= self.next {
+            return;
+        }
+
+        let end = self.next;
+        let in_set = |ptr: ObjPtr| (start..end).contains(&ptr.0.cast());
+
+        // mark the live objects in the collection set. the objects above the limit are never
+        // collected here, so their fields are roots just like those of the immortal objects.
+        let mut stack = self.root_referents();
+        let mut push_fields = |ptr: ObjPtr| {
+            let tag = unsafe { ptr.tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.field_kind(idx) == StellaFieldKind::Obj {
+                    stack.push(unsafe { *ptr.field(idx) });
+                }
+            }
+        };
+
+        self.immortal.for_each_object(&mut push_fields);
+        self.for_each_object_between(self.limit, self.to_space.end(), &mut push_fields);
+
+        self.remembered
+            .retain(|&(ptr, idx)| !in_set(ptr) && idx < unsafe { ptr.field_count() });
+        stack.extend(
+            self.remembered
+                .iter()
+                .map(|&(ptr, idx)| unsafe { *ptr.field(idx) }),
+        );
+
+        let mut live = HashSet::new();
+
+        while let Some(ptr) = stack.pop() {
+            if !in_set(ptr) || !live.insert(ptr) {
+                continue;
+            }
+
+            let tag = unsafe { ptr.tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.field_kind(idx) == StellaFieldKind::Obj {
+                    stack.push(unsafe { *ptr.field(idx) });
+                }
+            }
+        }
+
+        // assign new addresses to the survivors.
+        let mut moves = vec![];
+        let mut forwarding = HashMap::new();
+        let mut cursor = start;
+        let mut addr = start;
+
+        while addr < end {
+            let ptr = ObjPtr(addr.cast());
+            let size = unsafe { self.footprint(ptr) };
+
+            if live.contains(&ptr) {
+                let align = self.obj_info.get(&ptr.0).copied().unwrap_or_default().align;
+                let new_addr =
+                    cursor.wrapping_byte_add(align_up(cursor.addr(), align) - cursor.addr());
+                moves.push((ptr, ObjPtr(new_addr.cast()), size));
+                forwarding.insert(ptr, ObjPtr(new_addr.cast()));
+                cursor = unsafe { new_addr.byte_add(size) };
+            }
+
+            addr = unsafe { addr.byte_add(size) };
+        }
+
+        if !self.finalizers.is_empty() {
+            let is_dead = |ptr: ObjPtr| in_set(ptr) && !live.contains(&ptr);
+            let mut dying = vec![];
+
+            for (addr, finalizer) in mem::take(&mut self.finalizers) {
+                let ptr = ObjPtr(addr);
+
+                if is_dead(ptr) {
+                    dying.push((ptr, finalizer));
+                } else {
+                    let addr = forwarding.get(&ptr).map_or(addr, |new_ptr| new_ptr.0);
+                    self.finalizers.insert(addr, finalizer);
+                }
+            }
+
+            let finalizers = dying.iter().copied().collect::<HashMap<_, _>>();
+            let order = unsafe {
+                self.order_finalization(dying.into_iter().map(|(ptr, _)| ptr).collect(), is_dead)
+            };
+            self.ready_finalizers
+                .extend(order.into_iter().map(|ptr| finalizers[&ptr]));
+        }
+
+        // update the references to the survivors.
+        let relocate = |slot: *mut ObjPtr| unsafe {
+            if let Some(&new_ptr) = forwarding.get(&*slot) {
+                ptr::write(slot, new_ptr);
+            }
+        };
+
+        for &root in &self.roots {
+            relocate(root);
+        }
+
+        for root in self.value_roots.iter_mut().flatten() {
+            relocate(root);
+        }
+
+        for &(ptr, idx) in &self.remembered {
+            relocate(unsafe { ptr.field(idx) });
+        }
+
+        let relocate_fields = |ptr: ObjPtr| {
+            let tag = unsafe { ptr.tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.field_kind(idx) == StellaFieldKind::Obj {
+                    relocate(unsafe { ptr.field(idx) });
+                }
+            }
+        };
+
+        self.immortal.for_each_object(relocate_fields);
+        self.for_each_object_between(self.limit, self.to_space.end(), relocate_fields);
+
+        for &(ptr, _, _) in &moves {
+            relocate_fields(ptr);
+        }
+
+        // slide the survivors down; they can only move to lower addresses, so copying them in
+        // address order never overwrites an object that's yet to be moved.
+        let mut moved_info = vec![];
+        let mut prev_end = start;
+
+        for &(old_ptr, new_ptr, size) in &moves {
+            unsafe { fill_padding(prev_end, new_ptr.0.cast()) };
+            unsafe { ptr::copy(old_ptr.0.cast::<u8>(), new_ptr.0.cast::<u8>(), size) };
+            unsafe { self.init_forwarding_word(new_ptr, new_ptr.size()) };
+            prev_end = unsafe { new_ptr.0.byte_add(size) }.cast();
+
+            if let Some(info) = self.obj_info.remove(&old_ptr.0) {
+                moved_info.push((new_ptr.0, info));
+            }
+        }
+
+        self.obj_info
+            .retain(|&addr, _| !(start..end).contains(&addr.cast()));
+        self.obj_info.extend(moved_info);
+
+        self.next = cursor;
+        self.minor_floor = cursor;
+        self.remembered.clear();
+        self.stats.minor_cycles += 1;
+    }
+
+    /// Returns how much memory (in bytes) is used in the to-space.
+    fn to_space_used_memory(&self) -> usize {
+        unsafe {
+            self.to_space.end().byte_offset_from_unsigned(self.limit)
+                + self.next.byte_offset_from_unsigned(self.to_space.start)
+        }
+    }
+
+    /// Returns the size of the largest object [`Gc::alloc`] could ever allocate with the current
+    /// heap size, which is what fits in an empty semi-space.
+    fn max_single_alloc(&self) -> usize {
+        let max_size = self.space_size.max(self.to_space.size);
+
+        align_down(max_size.saturating_sub(self.footprint_for(0)), ALIGNMENT)
+    }
+
+    /// Returns how much free memory remains before the next GC cycle begins.
+    fn free_memory(&self) -> usize {
+        unsafe { self.limit.byte_offset_from_unsigned(self.next) }
+    }
+
+    /// Returns how much memory is used in the both semi-spaces.
+    fn used_memory(&self) -> usize {
+        let to_space_used = self.to_space_used_memory();
+
+        self.from_space
+            .as_ref()
+            .map(|space| space.size)
+            .unwrap_or(0)
+            + to_space_used
+    }
+
+    /// Returns `true` if `ptr` has been forwarded to the to-space.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn is_forwarded(&self, ptr: ObjPtr) -> bool {
+        self.from_space
+            .as_ref()
+            .is_some_and(|from_space| from_space.contains(ptr.0.cast()))
+            && self
+                .to_space
+                .contains(unsafe { self.forwardee(ptr) }.0.cast())
+    }
+
+    /// Calls `f` for every object residing in the to-space, skipping padding fillers.
+    ///
+    /// Objects are visited in address order. Note that unless a GC cycle has just finished, some of
+    /// them may already be unreachable.
+    fn for_each_object(&self, mut f: impl FnMut(ObjPtr)) {
+        self.for_each_object_between(self.to_space.start, self.next, &mut f);
+        self.for_each_object_between(self.limit, self.to_space.end(), f);
+    }
+
+    /// Calls `f` for every object in the part `start..end` of the to-space, skipping padding
+    /// fillers.
+    ///
+    /// Both bounds must lie on object boundaries.
+    fn for_each_object_between(&self, start: *mut u8, end: *mut u8, mut f: impl FnMut(ObjPtr)) {
+        let mut addr = start;
+
+        while addr < end {
+            let ptr = ObjPtr(addr.cast());
+
+            if !unsafe { ptr.is_padding() } {
+                f(ptr);
+            }
+
+            addr = unsafe { addr.byte_add(self.footprint(ptr)) };
+        }
+    }
+
+    /// Checks that no object in the to-space or the immortal space has an `Obj` field pointing to the
+    /// from-space.
+    ///
+    /// # Panics
+    /// Panics if such a field is found, after printing the offending object.
+    ///
+    /// # Safety
+    /// Must only be called at the end of a GC cycle, once the to-space has been fully scanned.
+    #[cfg(feature = "verify-after-gc")]
+    unsafe fn verify_no_from_space_refs(&self) {
+        let Some(from_space) = &self.from_space else {
+            return;
+        };
+
+        let check = |ptr: ObjPtr| {
+            let tag = unsafe { ptr.try_tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                let field = unsafe { *ptr.field(idx) };
+
+                // like the read barrier, assume the fields of unknown objects aren't references.
+                if tag.is_some_and(|tag| tag.field_kind(idx) == StellaFieldKind::Obj)
+                    && from_space.contains(field.0.cast())
+                    && !self.is_pinned(field)
+                {
+                    eprintln!("{}", unsafe { self.display_obj(ptr, false) });
+                    panic!(
+                        "field #{idx} of {:?} points to the from-space after GC",
+                        ptr.0
+                    );
+                }
+            }
+        };
+
+        self.for_each_object(check);
+        self.immortal.for_each_object(check);
+    }
+
+    /// Checks every object in the to-space and the immortal space with [`Gc::validate_object`].
+    ///
+    /// # Panics
+    /// Panics if an object fails the check, after printing it.
+    fn verify_heap(&self) {
+        let check = |ptr: ObjPtr| {
+            if let Err(defect) = self.validate_object(ptr) {
+                eprintln!("{}", unsafe { self.display_obj(ptr, true) });
+                panic!(
+                    "heap verification after GC cycle #{} failed: {:?} is {defect}",
+                    self.stats.gc_cycles, ptr.0,
+                );
+            }
+        };
+
+        self.for_each_object(check);
+        self.immortal.for_each_object(check);
+    }
+
+    /// Calls `f` once for every managed object reachable from `roots` via `Obj` fields.
+    ///
+    /// Unmanaged objects are neither reported nor traversed.
+    ///
+    /// # Safety
+    /// Must only be called when `self.gc_in_progress` is `false`. The roots must point to valid
+    /// stella objects.
+    unsafe fn for_each_reachable(
+        &self,
+        roots: impl IntoIterator<Item = ObjPtr>,
+        mut f: impl FnMut(ObjPtr),
+    ) {
+        let mut visited = HashSet::new();
+        let mut stack = roots.into_iter().collect::<Vec<_>>();
+
+        while let Some(ptr) = stack.pop() {
+            if self.classify_space(ptr.0) == SpaceClass::Unmanaged || !visited.insert(ptr) {
+                continue;
+            }
+
+            f(ptr);
+
+            let tag = unsafe { ptr.tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.field_kind(idx) == StellaFieldKind::Obj {
+                    stack.push(unsafe { *ptr.field(idx) });
+                }
+            }
+        }
+    }
+
+    /// Returns the total size of the managed objects reachable from `ptr`, counting each object
+    /// once.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn reachable_bytes_from(&self, ptr: ObjPtr) -> usize {
+        assert!(
+            !self.gc_in_progress,
+            "cannot traverse the heap while GC is in progress",
+        );
+
+        let mut result = 0;
+        unsafe { self.for_each_reachable([ptr], |ptr| result += ptr.size()) };
+
+        result
+    }
+
+    /// Returns the number of managed objects reachable from the roots (including the value roots)
+    /// and the immortal objects and their total size, counting each object once.
+    ///
+    /// The fields of the immortal objects are treated as roots, like during a collection, so the
+    /// immortal objects are always counted.
+    ///
+    /// Nothing is moved or written to: the visited objects are tracked in a side table.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    ///
+    /// # Safety
+    /// The roots must point to valid stella objects.
+    unsafe fn mark_and_count(&self) -> (usize, usize) {
+        assert!(
+            !self.gc_in_progress,
+            "cannot traverse the heap while GC is in progress",
+        );
+
+        let mut roots = self.root_referents();
+        self.immortal.for_each_object(|ptr| roots.push(ptr));
+
+        let mut objs = 0;
+        let mut bytes = 0;
+
+        unsafe {
+            self.for_each_reachable(roots, |ptr| {
+                objs += 1;
+                bytes += ptr.size();
+            })
+        };
+
+        (objs, bytes)
+    }
+
+    /// Estimates the number of bytes that would survive a GC cycle started right now.
+    ///
+    /// This is the total footprint of the to-space objects reachable from the roots and the
+    /// immortal objects, counting each object once. Padding needed for over-aligned objects isn't
+    /// accounted for.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    ///
+    /// # Safety
+    /// The roots must point to valid stella objects.
+    unsafe fn estimate_live_after_gc(&self) -> usize {
+        assert!(
+            !self.gc_in_progress,
+            "cannot traverse the heap while GC is in progress",
+        );
+
+        let mut roots = self.root_referents();
+        self.immortal.for_each_object(|ptr| roots.push(ptr));
+
+        let mut result = 0;
+
+        unsafe {
+            self.for_each_reachable(roots, |ptr| {
+                if self.to_space.contains(ptr.0.cast()) {
+                    result += self.footprint(ptr);
+                }
+            })
+        };
+
+        result
+    }
+
+    /// Computes a digest of the objects reachable from the roots and the immortal objects.
+    ///
+    /// The digest covers the header and the fields of every managed object. Raw fields are hashed
+    /// by value, and `Obj` fields pointing to managed objects are hashed as offsets of their
+    /// targets in a canonical layout, where the objects are laid out back to back in traversal
+    /// order. Other `Obj` fields are hashed by address. The digest therefore doesn't change when a
+    /// collection moves the objects around as long as the structure of the heap stays the same.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    ///
+    /// # Safety
+    /// The roots must point to valid stella objects.
+    unsafe fn heap_checksum(&self) -> u64 {
+        assert!(
+            !self.gc_in_progress,
+            "cannot traverse the heap while GC is in progress",
+        );
+
+        let mut roots = self.root_referents();
+        self.immortal.for_each_object(|ptr| roots.push(ptr));
+
+        let mut objs = vec![];
+        let mut offsets = HashMap::new();
+        let mut offset = 0;
+
+        unsafe {
+            self.for_each_reachable(roots.iter().copied(), |ptr| {
+                objs.push(ptr);
+                offsets.insert(ptr, offset);
+                offset += ptr.size();
+            })
+        };
+
+        let mut hasher = DefaultHasher::new();
+
+        for &root in &roots {
+            offsets
+                .get(&root)
+                .copied()
+                .unwrap_or(root.0.addr())
+                .hash(&mut hasher);
+        }
+
+        for ptr in objs {
+            unsafe { (*ptr.0).header }.hash(&mut hasher);
+            let tag = unsafe { ptr.tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                let value = unsafe { *ptr.field(idx) };
+
+                match tag.field_kind(idx) {
+                    StellaFieldKind::Obj => offsets.get(&value).copied().unwrap_or(value.0.addr()),
+                    StellaFieldKind::Raw | StellaFieldKind::Invalid => value.0.addr(),
+                }
+                .hash(&mut hasher);
+            }
+        }
+
+        hasher.finish()
+    }
+
+    /// Renders the managed objects reachable from `ptr` as a Graphviz digraph.
+    ///
+    /// Every object is drawn once, labeled with its tag and address, and every `Obj` field is drawn
+    /// as an edge labeled with the field's index. Unmanaged objects are drawn with a dashed outline
+    /// and aren't traversed.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object.
+    unsafe fn object_graph_dot(&self, ptr: ObjPtr) -> String {
+        assert!(
+            !self.gc_in_progress,
+            "cannot traverse the heap while GC is in progress",
+        );
+
+        let mut nodes = String::new();
+        let mut edges = String::new();
+        let mut unmanaged = HashSet::new();
+
+        let write_node = |nodes: &mut String, ptr: ObjPtr, style: &str| {
+            let tag = match unsafe { ptr.try_tag() } {
+                Some(tag) => tag.to_string(),
+                None => format!("unknown-tag:{:#04x}", unsafe { ptr.raw_tag() }),
+            };
+
+            writeln!(
+                nodes,
+                "  \"{:?}\" [label=\"{tag}\\n{:?}\"{style}];",
+                ptr.0, ptr.0
+            )
+            .unwrap();
+        };
+
+        unsafe {
+            self.for_each_reachable([ptr], |ptr| {
+                write_node(&mut nodes, ptr, "");
+                let tag = ptr.tag();
+
+                for idx in 0..ptr.field_count() {
+                    let field = *ptr.field(idx);
+
+                    if tag.field_kind(idx) != StellaFieldKind::Obj || field.0.is_null() {
+                        continue;
+                    }
+
+                    writeln!(
+                        edges,
+                        "  \"{:?}\" -> \"{:?}\" [label=\"{idx}\"];",
+                        ptr.0, field.0
+                    )
+                    .unwrap();
+
+                    if self.classify_space(field.0) == SpaceClass::Unmanaged {
+                        unmanaged.insert(field);
+                    }
+                }
+            });
+        }
+
+        if self.classify_space(ptr.0) == SpaceClass::Unmanaged {
+            unmanaged.insert(ptr);
+        }
+
+        for ptr in unmanaged {
+            write_node(&mut nodes, ptr, ", style=dashed");
+        }
+
+        format!("digraph heap {{\n  node [shape=box];\n{nodes}{edges}}}\n")
+    }
+
+    /// Takes a snapshot of the statistics for `gc_get_stats`.
+    fn stats_snapshot(&self) -> GcStatsFfi {
+        GcStatsFfi {
+            reads: READS.load(Ordering::Relaxed),
+            writes: self.stats.writes,
+            read_barriers: self.stats.read_barriers,
+            write_barriers: self.stats.write_barriers,
+            eager_forwards: self.stats.eager_forwards,
+            allocated_bytes: self.stats.all_time_allocated,
+            allocated_objs: self.stats.all_time_allocated_objs,
+            evacuated_bytes: self.stats.evacuated_bytes,
+            evacuated_objs: self.stats.evacuated_objs,
+            promoted_bytes: self.stats.promoted_bytes,
+            promoted_objs: self.stats.promoted_objs,
+            padding_bytes: self.stats.padding_bytes,
+            gc_cycles: self.stats.gc_cycles,
+            minor_cycles: self.stats.minor_cycles,
+            max_used: self.stats.max_used,
+            max_to_space_used: self.stats.max_to_space_used,
+        }
+    }
+
+    /// Returns a stamp that changes whenever an object is allocated, a collection happens, or
+    /// objects are promoted.
+    ///
+    /// Read barriers may still evacuate objects without changing the stamp: they don't invalidate
+    /// any pointers the mutator holds.
+    fn mutation_stamp(&self) -> (usize, usize, usize, usize) {
+        (
+            self.stats.all_time_allocated_objs,
+            self.stats.gc_cycles,
+            self.stats.minor_cycles,
+            self.stats.promoted_objs,
+        )
+    }
+
+    /// Checks that the heap has not been mutated since `stamp` was taken by
+    /// [`Gc::mutation_stamp`].
+    ///
+    /// # Panics
+    /// Panics if an object has been allocated or a collection has happened since then.
+    fn assert_not_mutated(&self, stamp: (usize, usize, usize, usize)) {
+        assert!(
+            self.mutation_stamp() == stamp,
+            "heap mutated during iteration"
+        );
+    }
+
+    /// Returns the objects in the to-space and the immortal space that were allocated in `epoch` or
+    /// later.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    fn objects_since_epoch(&self, epoch: u64) -> Vec<ObjPtr> {
+        assert!(
+            !self.gc_in_progress,
+            "cannot enumerate objects while GC is in progress",
+        );
+
+        let mut result = vec![];
+        let mut visit = |ptr: ObjPtr| {
+            let info = self.obj_info.get(&ptr.0).copied().unwrap_or_default();
+
+            if info.epoch >= epoch {
+                result.push(ptr);
+            }
+        };
+
+        self.for_each_object(&mut visit);
+        self.immortal.for_each_object(&mut visit);
+
+        result
+    }
+
+    /// Creates a deep copy of the object graph reachable from `src` via `Obj` fields.
+    ///
+    /// Every managed object in the graph is copied exactly once, so shared substructure and cycles
+    /// are reproduced faithfully. `Raw` fields and references to unmanaged objects are copied
+    /// verbatim. If `src` itself is unmanaged, it's returned as is.
+    ///
+    /// Both the original objects and the copies are registered as roots while the copy is being
+    /// made, since allocating the copies may start or continue a GC cycle.
+    ///
+    /// # Safety
+    /// `src` must point to a valid stella object. All roots must have already been registered in
+    /// the root stack.
+    unsafe fn copy_graph(&mut self, src: ObjPtr) -> ObjPtr {
+        let src = unsafe { self.forward(src) };
+
+        if self.classify_space(src.0) == SpaceClass::Unmanaged {
+            return src;
+        }
+
+        // discover the graph, recording the edges as `(field index, target index)` pairs.
+        let mut srcs = vec![src];
+        let mut indices = HashMap::from([(src, 0)]);
+        let mut edges = vec![];
+
+        while let Some(&ptr) = srcs.get(edges.len()) {
+            let tag = unsafe { ptr.tag() };
+            let mut obj_edges = vec![];
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.field_kind(idx) != StellaFieldKind::Obj {
+                    continue;
+                }
+
+                // forward the field the same way the read barrier would.
+                let field_ptr = unsafe { ptr.field(idx) };
+                let field = unsafe { self.forward(*field_ptr) };
+                unsafe { ptr::write(field_ptr, field) };
+
+                if self.classify_space(field.0) == SpaceClass::Unmanaged {
+                    continue;
+                }
+
+                let target = *indices.entry(field).or_insert_with(|| {
+                    srcs.push(field);
+
+                    srcs.len() - 1
+                });
+                obj_edges.push((idx, target));
+            }
+
+            edges.push(obj_edges);
+        }
+
+        let mut srcs = srcs.into_boxed_slice();
+        let mut copies = vec![ObjPtr(ptr::null_mut()); srcs.len()].into_boxed_slice();
+        let roots_len = self.roots.len();
+        self.roots
+            .extend(srcs.iter_mut().chain(copies.iter_mut()).map(ptr::from_mut));
+
+        // allocate the copies, leaving the edges null so none of them point to the from-space.
+        for (idx, obj_edges) in edges.iter().enumerate() {
+            let src = srcs[idx];
+            let size = unsafe { src.size() };
+            let align = self.obj_info.get(&src.0).copied().unwrap_or_default().align;
+            let copy = unsafe { self.alloc_aligned(size, align) };
+
+            // the source may have been moved by the allocation.
+            let src = srcs[idx];
+            unsafe { ptr::copy(src.0.cast::<u8>(), copy.0.cast::<u8>(), size) };
+
+            for &(field_idx, _) in obj_edges {
+                unsafe { ptr::write(copy.field(field_idx), ObjPtr(ptr::null_mut())) };
+            }
+
+            copies[idx] = copy;
+        }
+
+        // no more allocations happen past this point, so nothing is going to move.
+        for (idx, obj_edges) in edges.iter().enumerate() {
+            for &(field_idx, target) in obj_edges {
+                unsafe { ptr::write(copies[idx].field(field_idx), copies[target]) };
+            }
+        }
+
+        self.roots.truncate(roots_len);
+
+        copies[0]
+    }
+
+    /// Moves `ptr` to the immortal space, along with every to-space object reachable from it if
+    /// `deep` is set, and returns its new address.
+    ///
+    /// Every reference to the moved objects is updated: in the roots (including the value roots),
+    /// the fields of the to-space and immortal objects, and the GC's own side tables. The old
+    /// copies are left behind as garbage. Objects outside the to-space are returned as is.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    ///
+    /// # Safety
+    /// `ptr` must point to a valid stella object, and so must the roots.
+    unsafe fn promote(&mut self, ptr: ObjPtr, deep: bool) -> ObjPtr {
+        assert!(
+            !self.gc_in_progress,
+            "cannot promote objects while GC is in progress",
+        );
+
+        if !self.to_space.contains(ptr.0.cast()) {
+            return ptr;
+        }
+
+        let mut objs = vec![];
+
+        if deep {
+            unsafe {
+                self.for_each_reachable([ptr], |obj| {
+                    if self.to_space.contains(obj.0.cast()) {
+                        objs.push(obj);
+                    }
+                })
+            };
+        } else {
+            objs.push(ptr);
+        }
+
+        let mut forwarding = HashMap::with_capacity(objs.len());
+
+        for obj in objs {
+            let size = unsafe { obj.size() };
+            let info = self.obj_info.remove(&obj.0);
+            let align = info.unwrap_or_default().align;
+            let copy = ObjPtr(self.immortal.alloc_aligned(size, align).cast());
+            unsafe { ptr::copy_nonoverlapping(obj.0.cast::<u8>(), copy.0.cast::<u8>(), size) };
+
+            if let Some(info) = info {
+                self.obj_info.insert(copy.0, info);
+            }
+
+            if let Some(finalizer) = self.finalizers.remove(&obj.0) {
+                self.finalizers.insert(copy.0, finalizer);
+            }
+
+            self.stats.promoted_objs += 1;
+
+            if stats_enabled() {
+                self.stats.promoted_bytes += size;
+            }
+
+            forwarding.insert(obj, copy);
+        }
+
+        let relocate = |slot: *mut ObjPtr| unsafe {
+            if let Some(&copy) = forwarding.get(&*slot) {
+                ptr::write(slot, copy);
+            }
+        };
+
+        for &root in &self.roots {
+            relocate(root);
+        }
+
+        for root in self.value_roots.iter_mut().flatten() {
+            relocate(root);
+        }
+
+        let relocate_fields = |obj: ObjPtr| {
+            let Some(tag) = (unsafe { obj.try_tag() }) else {
+                return;
+            };
+
+            for idx in 0..unsafe { obj.field_count() } {
+                if tag.field_kind(idx) == StellaFieldKind::Obj {
+                    relocate(unsafe { obj.field(idx) });
+                }
+            }
+        };
+
+        self.for_each_object(relocate_fields);
+        self.immortal.for_each_object(relocate_fields);
+
+        // the immortal objects' fields are roots for minor collections anyway.
+        self.remembered
+            .retain(|(obj, _)| !forwarding.contains_key(obj));
+
+        forwarding[&ptr]
+    }
+
+    /// Serializes the object graph reachable from `root` into a flat buffer.
+    ///
+    /// `root` and every GC-managed object reachable from it are laid out back to back, starting
+    /// with `root` at offset 0, in the same format they have in memory (except that an object
+    /// without fields takes up just its header, see [`MIN_OBJ_SIZE`]). `Obj` fields pointing to
+    /// the serialized objects are replaced with their offsets in the buffer with the lowest bit
+    /// set. Raw fields and references to unmanaged objects are copied verbatim.
+    ///
+    /// # Safety
+    /// `root` must point to a valid stella object.
+    unsafe fn export_graph(&mut self, root: ObjPtr) -> Vec<u8> {
+        let root = unsafe { self.forward(root) };
+
+        // discover the graph, recording the edges as `(field index, target index)` pairs.
+        let mut objs = vec![root];
+        let mut indices = HashMap::from([(root, 0)]);
+        let mut edges = vec![];
+
+        while let Some(&ptr) = objs.get(edges.len()) {
+            let tag = unsafe { ptr.tag() };
+            let mut obj_edges = vec![];
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.field_kind(idx) != StellaFieldKind::Obj {
+                    continue;
+                }
+
+                // forward the field the same way the read barrier would.
+                let field_ptr = unsafe { ptr.field(idx) };
+                let field = unsafe { self.forward(*field_ptr) };
+                unsafe { ptr::write(field_ptr, field) };
+
+                if self.classify_space(field.0) == SpaceClass::Unmanaged && field != root {
+                    continue;
+                }
+
+                let target = *indices.entry(field).or_insert_with(|| {
+                    objs.push(field);
+
+                    objs.len() - 1
+                });
+                obj_edges.push((idx, target));
+            }
+
+            edges.push(obj_edges);
+        }
+
+        let mut offsets = Vec::with_capacity(objs.len());
+        let mut result = vec![];
+
+        for &ptr in &objs {
+            // the format has no room for the unused field of an empty object.
+            let size = field_offset(unsafe { ptr.field_count() }).unwrap();
+            offsets.push(result.len());
+            result.extend_from_slice(unsafe { slice::from_raw_parts(ptr.0.cast::<u8>(), size) });
+        }
+
+        for (idx, obj_edges) in edges.iter().enumerate() {
+            for &(field_idx, target) in obj_edges {
+                let at = offsets[idx] + field_offset(field_idx).unwrap();
+                result[at..at + FIELD_SIZE].copy_from_slice(&(offsets[target] | 1).to_ne_bytes());
+            }
+        }
+
+        result
+    }
+
+    /// Runs a full GC cycle to completion and serializes the surviving heap into a flat buffer.
+    ///
+    /// The buffer has the format of [`Gc::export_graph`] and holds every object in the to-space,
+    /// in address order, followed by the immortal objects. The objects are recorded as the cycle
+    /// evacuates them (see [`Gc::record_evacuated`]), which covers all of the survivors since the
+    /// cycle in progress, if any, is finished first, and nothing is allocated during the full one.
+    /// Only the immortal objects (including the pinned ones) are appended afterwards.
+    ///
+    /// # Panics
+    /// Panics if collection is suppressed (see [`Gc::cycle_pins`]) or the new to-space can't be
+    /// allocated.
+    ///
+    /// # Safety
+    /// All roots must have already been registered in the root stack.
+    unsafe fn collect_into_snapshot(&mut self) -> Vec<u8> {
+        unsafe { self.finish_gc() };
+        self.snapshot = Some(Default::default());
+        unsafe { self.collect() };
+        let mut recorder = self.snapshot.take().unwrap();
+
+        let mut immortal = vec![];
+        self.immortal.for_each_object(|ptr| immortal.push(ptr));
+        let mut immortal_offsets = HashMap::with_capacity(immortal.len());
+
+        for &ptr in &immortal {
+            let offset = unsafe { recorder.push(ptr) };
+            immortal_offsets.insert(ptr, offset);
+
+            // the pinned objects stay where they were in the from-space.
+            for at in recorder.pending.remove(&ptr).unwrap_or_default() {
+                recorder.set_field(at, offset | 1);
+            }
+        }
+
+        for &ptr in &immortal {
+            let tag = unsafe { ptr.try_tag() };
+
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.is_none_or(|tag| tag.field_kind(idx) != StellaFieldKind::Obj) {
+                    continue;
+                }
+
+                let field = unsafe { *ptr.field(idx) };
+
+                if let Some(&target) = recorder
+                    .offsets
+                    .get(&field)
+                    .or_else(|| immortal_offsets.get(&field))
+                {
+                    let at = immortal_offsets[&ptr] + field_offset(idx).unwrap();
+                    recorder.set_field(at, target | 1);
+                }
+            }
+        }
+
+        for (at, field) in mem::take(&mut recorder.outside) {
+            if let Some(&target) = immortal_offsets.get(&field) {
+                recorder.set_field(at, target | 1);
+            }
+        }
+
+        // the referents that were never evacuated have died, and so the fields have been cleared.
+        for at in mem::take(&mut recorder.pending).into_values().flatten() {
+            recorder.set_field(at, 0);
+        }
+
+        recorder.buf
+    }
+
+    /// Recreates on the GC heap an object graph serialized by [`Gc::export_graph`].
+    ///
+    /// Returns the copy of the object at offset 0, or `None` if the buffer is malformed or there's
+    /// not enough memory, in which case the reason is recorded in [`Gc::last_error`].
+    fn import_graph(&mut self, buf: &[u8]) -> Option<ObjPtr> {
+        // parse the objects as `(offset, tag, field count)` triples.
+        let mut objs = vec![];
+        let mut indices = HashMap::new();
+        let mut offset = 0;
+
+        while offset < buf.len() {
+            let Some(header) = buf
+                .get(offset..offset + mem::size_of::<Header>())
+                .map(|bytes| Header::from_ne_bytes(bytes.try_into().unwrap()))
+            else {
+                self.set_last_error(format_args!("truncated object header at offset {offset}"));
+
+                return None;
+            };
+
+            let raw_tag = (header & unsafe { TAG_MASK }) as usize;
+            let field_count = (header & unsafe { FIELD_COUNT_MASK }) as usize >> 4;
+
+            let Some(tag) = StellaTag::from_repr(raw_tag) else {
+                self.set_last_error(format_args!(
+                    "unknown tag {raw_tag:#04x} of the object at offset {offset}",
+                ));
+
+                return None;
+            };
+
+            let end = field_offset(field_count).and_then(|size| offset.checked_add(size));
+
+            if end.is_none_or(|end| end > buf.len()) {
+                self.set_last_error(format_args!(
+                    "the object at offset {offset} with {field_count} fields is truncated",
+                ));
+
+                return None;
+            }
+
+            indices.insert(offset, objs.len());
+            objs.push((offset, tag, field_count));
+            offset = end.unwrap();
+        }
+
+        if objs.is_empty() {
+            self.set_last_error("the buffer is empty");
+
+            return None;
+        }
+
+        let read_field = |offset: usize, idx: usize| {
+            let at = offset + field_offset(idx).unwrap();
+
+            usize::from_ne_bytes(buf[at..at + FIELD_SIZE].try_into().unwrap())
+        };
+
+        // validate the references before allocating anything.
+        for &(offset, tag, field_count) in &objs {
+            for idx in 0..field_count {
+                let value = read_field(offset, idx);
+
+                if tag.field_kind(idx) != StellaFieldKind::Obj {
+                    continue;
+                }
+
+                if value & 1 != 0 {
+                    if !indices.contains_key(&(value & !1)) {
+                        self.set_last_error(format_args!(
+                            "field #{idx} of the object at offset {offset} refers to offset {}, \
+                             where no object starts",
+                            value & !1,
+                        ));
+
+                        return None;
+                    }
+                } else if self.classify_space(ptr::without_provenance_mut(value))
+                    != SpaceClass::Unmanaged
+                {
+                    self.set_last_error(format_args!(
+                        "field #{idx} of the object at offset {offset} points into the GC heap",
+                    ));
+
+                    return None;
+                }
+            }
+        }
+
+        let mut copies = vec![ObjPtr(ptr::null_mut()); objs.len()].into_boxed_slice();
+        let roots_len = self.roots.len();
+        self.roots.extend(copies.iter_mut().map(ptr::from_mut));
+
+        // allocate the copies, leaving the internal references null so none of them point to the
+        // from-space.
+        for (copy_idx, &(offset, tag, field_count)) in objs.iter().enumerate() {
+            let Some(copy) = self.alloc_tagged(tag, field_count) else {
+                self.roots.truncate(roots_len);
+
+                return None;
+            };
+
+            for idx in 0..field_count {
+                let value = read_field(offset, idx);
+
+                if tag.field_kind(idx) != StellaFieldKind::Obj || value & 1 == 0 {
+                    let value = ObjPtr(ptr::with_exposed_provenance_mut(value));
+                    unsafe { ptr::write(copy.field(idx), value) };
+                }
+            }
+
+            copies[copy_idx] = copy;
+        }
+
+        // no more allocations happen past this point, so nothing is going to move.
+        for (copy_idx, &(offset, tag, field_count)) in objs.iter().enumerate() {
+            for idx in 0..field_count {
+                let value = read_field(offset, idx);
+
+                if tag.field_kind(idx) == StellaFieldKind::Obj && value & 1 != 0 {
+                    let target = copies[indices[&(value & !1)]];
+                    unsafe { ptr::write(copies[copy_idx].field(idx), target) };
+                }
+            }
+        }
+
+        self.roots.truncate(roots_len);
+
+        Some(copies[0])
+    }
+
+    /// Summarizes how much of the memory reserved for the heap is actually used.
+    ///
+    /// Since the collector copies the survivors, unused memory is never scattered: it's the gap
+    /// between the used part of the to-space and its size, which is what the report shows along
+    /// with how soon the next GC cycle begins. The heap is judged by how much survived the last GC
+    /// cycle, since the to-space is always nearly full right before a cycle begins.
+    fn fragmentation_report(&self) -> String {
+        let used = self.to_space_used_memory();
+        let size = self.to_space.size;
+        let percent = used as f64 / size as f64 * 100.0;
+
+        let mut result = String::new();
+        writeln!(result, "Fragmentation report:").unwrap();
+        write_stats_disabled_note(&mut result);
+        writeln!(result, "  - Used: {} B in total", self.used_memory()).unwrap();
+        writeln!(
+            result,
+            "  - To-space: {used} B / {size} B used ({percent:.1}%)"
+        )
+        .unwrap();
+
+        if self.gc_in_progress {
+            writeln!(
+                result,
+                "  - GC cycle in progress: {} B free until the to-space runs out",
+                self.free_memory(),
+            )
+            .unwrap();
+        } else {
+            writeln!(
+                result,
+                "  - Next GC cycle begins in {} B of allocations",
+                self.free_memory(),
+            )
+            .unwrap();
+        }
+
+        let Some(survived) = self.last_survived else {
+            writeln!(result, "  - No GC cycle has finished yet").unwrap();
+
+            return result;
+        };
+
+        let percent = survived as f64 / size as f64 * 100.0;
+        let verdict = if percent < 25.0 {
+            "the heap may be larger than necessary"
+        } else if percent > 90.0 {
+            "collections may be happening too often"
+        } else {
+            "the heap is reasonably sized"
+        };
+
+        writeln!(
+            result,
+            "  - Survived the last GC cycle: {survived} B ({percent:.1}% of the to-space); {verdict}",
+        )
+        .unwrap();
+
+        result
+    }
+
+    /// Counts the live objects by their size class (see [`size_class`]).
+    ///
+    /// The live objects are the ones reachable from the roots and the immortal objects, so garbage
+    /// allocated since the last cycle isn't counted.
+    ///
+    /// # Panics
+    /// Panics if GC is in progress.
+    ///
+    /// # Safety
+    /// The roots must point to valid stella objects.
+    unsafe fn size_histogram(&self) -> [usize; SIZE_CLASSES] {
+        assert!(
+            !self.gc_in_progress,
+            "cannot traverse the heap while GC is in progress",
+        );
+
+        let mut roots = self.root_referents();
+        self.immortal.for_each_object(|ptr| roots.push(ptr));
+
+        let mut histogram = [0; SIZE_CLASSES];
+
+        unsafe {
+            self.for_each_reachable(roots, |ptr| {
+                histogram[size_class(ptr.size())] += 1;
+            })
+        };
+
+        histogram
+    }
+
+    /// Formats the allocation statistics printed by `print_gc_alloc_stats`.
+    fn alloc_stats_report(&self) -> String {
+        let mut result = String::new();
+        write_stats_disabled_note(&mut result);
+
+        writeln!(
+            result,
+            "  - All-time allocated: {} B ({} objects)",
+            self.stats.all_time_allocated, self.stats.all_time_allocated_objs,
+        )
+        .unwrap();
+        writeln!(
+            result,
+            "  - Allocated since the last cycle started: {} B ({} objects)",
+            self.stats.allocated_since_gc, self.stats.allocated_objs_since_gc,
+        )
+        .unwrap();
+        writeln!(
+            result,
+            "  - Evacuated: {} B ({} objects)",
+            self.stats.evacuated_bytes, self.stats.evacuated_objs,
+        )
+        .unwrap();
+        writeln!(
+            result,
+            "  - Promoted: {} B ({} objects)",
+            self.stats.promoted_bytes, self.stats.promoted_objs,
+        )
+        .unwrap();
+        writeln!(result, "  - Used:").unwrap();
+        writeln!(result, "    - Currently {} B", self.used_memory()).unwrap();
+        writeln!(result, "    - Max: {} B", self.stats.max_used).unwrap();
+        writeln!(
+            result,
+            "    - Max in the to-space: {} B",
+            self.stats.max_to_space_used,
+        )
+        .unwrap();
+        writeln!(
+            result,
+            "    - Max held by the semi-spaces: {} B",
+            self.stats.max_combined_used,
+        )
+        .unwrap();
+        writeln!(result, "  - Alignment padding: {} B", self.stats.padding_bytes).unwrap();
+        writeln!(
+            result,
+            "  - GC cycles: {}{}",
+            self.stats.gc_cycles,
+            if self.gc_in_progress {
+                " (currently in progress)"
+            } else {
+                ""
+            },
+        )
+        .unwrap();
+        writeln!(result, "  - Minor GC cycles: {}", self.stats.minor_cycles).unwrap();
+        writeln!(
+            result,
+            "  - Scan ratio: {:.3} B scanned per B allocated",
+            self.stats.scan_ratio,
+        )
+        .unwrap();
+        writeln!(
+            result,
+            "  - Reads: {} ({} barriers)",
+            READS.load(Ordering::Relaxed),
+            self.stats.read_barriers,
+        )
+        .unwrap();
+        writeln!(
+            result,
+            "  - Eagerly forwarded fields: {}",
+            self.stats.eager_forwards,
+        )
+        .unwrap();
+        writeln!(
+            result,
+            "  - Writes: {} ({} barriers)",
+            self.stats.writes, self.stats.write_barriers,
+        )
+        .unwrap();
+
+        result
+    }
+
+    /// Formats the objects located in the range `start..end` of a semi-space beginning at
+    /// `space_start`, appending them to `out`.
+    ///
+    /// Consecutive padding fillers are collapsed into a single line.
+    ///
+    /// # Safety
+    /// The range must be covered by valid stella objects and padding fillers.
+    unsafe fn write_objects(
+        &self,
+        out: &mut String,
+        space_name: &str,
+        space_start: *mut u8,
+        start: *mut u8,
+        end: *mut u8,
+    ) {
+        let mut addr = start;
+
+        while addr < end {
+            let ptr = ObjPtr(addr.cast());
+
+            if unsafe { ptr.is_padding() } {
+                let padding_start = addr;
+
+                while addr < end && unsafe { ObjPtr(addr.cast()).is_padding() } {
+                    addr = unsafe { addr.byte_add(PADDING_SIZE) };
+                }
+
+                writeln!(out, "    - {padding_start:?}..{addr:?} padding").unwrap();
+
+                continue;
+            }
+
+            let offset = unsafe { addr.byte_offset_from_unsigned(space_start) };
+            writeln!(out, "    - {addr:?} ({space_name}{offset:+}): {}", unsafe {
+                self.display_obj(ptr, true)
+            })
+            .unwrap();
+            addr = unsafe { addr.byte_add(self.footprint(ptr)) };
+        }
+    }
+
+    /// Formats the roots, one per line, each prefixed with `indent`.
+    ///
+    /// Roots pointing to unmanaged memory are flagged as illegal, and repeated entries for the same
+    /// slot are marked as duplicates.
+    fn write_roots(&self, out: &mut String, indent: &str) {
+        let mut seen = HashSet::new();
+
+        for &root in &self.roots {
+            let addr = unsafe { *root }.0;
+            let dup = if seen.insert(root) {
+                ""
+            } else {
+                " (duplicate)"
+            };
+
+            if self.classify_space(addr.cast()) == SpaceClass::Unmanaged {
+                writeln!(
+                    out,
+                    "{indent}**ILLEGAL** {root:?}{dup} points to {addr:?} (**unmanaged memory**)"
+                )
+                .unwrap();
+            } else {
+                writeln!(out, "{indent}{root:?}{dup} points to {}", unsafe {
+                    self.display_obj(*root, true)
+                })
+                .unwrap();
+            }
+        }
+    }
+
+    /// Formats the state of the heap printed by `print_gc_state`.
+    fn state_report(&self) -> String {
+        let mut result = String::new();
+
+        writeln!(result, "GC state:").unwrap();
+        write_stats_disabled_note(&mut result);
+
+        if let Some(from_space) = &self.from_space {
+            let start = from_space.start;
+            let end = from_space.end();
+
+            writeln!(result, "  - From-space ({start:?}..{end:?}):").unwrap();
+
+            unsafe { self.write_objects(&mut result, "from-space", start, start, end) };
+
+            writeln!(result).unwrap();
+        }
+
+        {
+            let start = self.to_space.start;
+            let end = self.to_space.end();
+            writeln!(result, "  - To-space ({start:?}..{end:?}):").unwrap();
+
+            unsafe { self.write_objects(&mut result, "to-space", start, start, self.next) };
+
+            let free_start = self.next;
+            let free_end = self.limit;
+
+            if free_start < free_end {
+                writeln!(result, "    - {free_start:?}..{free_end:?} free").unwrap();
+            }
+
+            unsafe { self.write_objects(&mut result, "to-space", start, self.limit, end) };
+        }
+
+        writeln!(result).unwrap();
+
+        if !self.immortal.chunks.is_empty() {
+            writeln!(
+                result,
+                "  - Immortal space ({} chunks):",
+                self.immortal.chunks.len(),
+            )
+            .unwrap();
+
+            self.immortal.for_each_object(|ptr| {
+                writeln!(result, "    - {:?}: {}", ptr.0, unsafe {
+                    self.display_obj(ptr, true)
+                })
+                .unwrap();
+            });
+
+            writeln!(result).unwrap();
+        }
+
+        if self.gc_in_progress {
+            writeln!(result, "  - Garbage collection currently in progress:").unwrap();
+            // `next` and `limit` may point one past the end, which `classify_space` won't accept.
+            let to_offset = |ptr: *mut u8| SpaceClass::To {
+                offset: unsafe { ptr.byte_offset_from_unsigned(self.to_space.start) },
+            };
+
+            for (name, ptr) in [
+                ("Scan", self.scan),
+                ("Next", self.next),
+                ("Limit", self.limit),
+            ] {
+                writeln!(result, "    - {name} pointer: {ptr:?} ({})", to_offset(ptr)).unwrap();
+            }
+        } else {
+            writeln!(result, "  - Garbage collection currently not running").unwrap();
+        }
+
+        writeln!(result).unwrap();
+
+        if self.roots.is_empty() {
+            writeln!(result, "  - Roots: (none)").unwrap();
+        } else {
+            writeln!(result, "  - Roots:").unwrap();
+            self.write_roots(&mut result, "    - ");
+        }
+
+        writeln!(result).unwrap();
+        writeln!(result, "  - Currently used: {} B", self.used_memory()).unwrap();
+
+        if let Some(from_space) = &self.from_space {
+            writeln!(
+                result,
+                "    - From-space: {} B / {} B used, 0 B free",
+                from_space.size, from_space.size,
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            result,
+            "    - To-space: {} B / {} B used, {} B free",
+            self.to_space_used_memory(),
+            self.to_space.size,
+            self.free_memory(),
+        )
+        .unwrap();
+
+        if !self.immortal.chunks.is_empty() {
+            writeln!(result, "    - Immortal space: {} B", self.immortal.used()).unwrap();
+        }
+
+        writeln!(result).unwrap();
+
+        result
+    }
+
+    /// Formats the roots printed by `print_gc_roots`.
+    fn roots_report(&self) -> String {
+        let mut result = String::new();
+        self.write_roots(&mut result, "");
+
+        result
+    }
+
+    /// Formats the one-line-per-root summary printed by `gc_print_roots_compact`.
+    fn roots_compact_report(&self) -> String {
+        let mut result = String::new();
+
+        for &root in &self.roots {
+            let obj = unsafe { *root };
+
+            if obj.0.is_null() {
+                writeln!(result, "{root:?} -> null").unwrap();
+
+                continue;
+            }
+
+            let tag = match unsafe { obj.try_tag() } {
+                Some(tag) => tag.to_string(),
+                None => format!("unknown-tag:{:#04x}", unsafe { obj.raw_tag() }),
+            };
+
+            writeln!(
+                result,
+                "{root:?} -> {:?} {tag} ({})",
+                obj.0,
+                self.classify_space(obj.0),
+            )
+            .unwrap();
+        }
+
+        result
+    }
+
+    /// Formats the size histogram printed by `gc_print_size_histogram`.
+    ///
+    /// Empty size classes are omitted. The live objects can't be told apart while GC is in
+    /// progress, in which case the report says so instead.
+    ///
+    /// # Safety
+    /// The roots must point to valid stella objects.
+    unsafe fn size_histogram_report(&self) -> String {
+        let mut result = String::new();
+
+        writeln!(result, "Size histogram:").unwrap();
+
+        if self.gc_in_progress {
+            writeln!(result, "  (unavailable while a GC cycle is in progress)").unwrap();
+
+            return result;
+        }
+
+        for (idx, &count) in unsafe { self.size_histogram() }.iter().enumerate() {
+            if count == 0 {
+                continue;
+            }
+
+            let max = 1usize << idx;
+            let min = max / 2 + 1;
+            writeln!(result, "  - {min}..={max} B: {count} objects").unwrap();
+        }
+
+        result
+    }
+
+    /// Formats the recorded GC events, oldest first, one per line.
+    #[cfg(feature = "event-log")]
+    fn event_log_report(&self) -> String {
+        let mut result = String::new();
+
+        for event in &self.event_log {
+            writeln!(result, "{event}").unwrap();
+        }
+
+        result
+    }
+
+    /// Determines the space class of the pointer.
+    fn classify_space(&self, ptr: *mut StellaObj) -> SpaceClass {
+        if let Some(from_space) = &self.from_space
+            && from_space.contains(ptr.cast())
+        {
+            SpaceClass::From {
+                offset: unsafe { ptr.byte_offset_from_unsigned(from_space.start) },
+            }
+        } else if self.to_space.contains(ptr.cast()) {
+            SpaceClass::To {
+                offset: unsafe { ptr.byte_offset_from_unsigned(self.to_space.start) },
+            }
+        } else if self.immortal.contains(ptr.cast()) {
+            SpaceClass::Immortal
+        } else {
+            SpaceClass::Unmanaged
+        }
+    }
+
+    /// Returns the end of the allocated area `ptr` points to, or `None` if it doesn't point to an
+    /// allocated part of a managed space.
+    fn allocated_end(&self, ptr: *mut u8) -> Option<*mut u8> {
+        if let Some(from_space) = &self.from_space
+            && from_space.contains(ptr)
+        {
+            Some(from_space.end())
+        } else if self.to_space.contains(ptr) {
+            if ptr < self.next {
+                Some(self.next)
+            } else if ptr >= self.limit {
+                Some(self.to_space.end())
+            } else {
+                None
+            }
+        } else {
+            self.immortal.allocated_end(ptr)
+        }
+    }
+
+    /// Checks that `ptr` looks like a valid object without walking the rest of the heap.
+    ///
+    /// The object must lie entirely in an allocated part of a managed space and have a known tag
+    /// with a matching field count. Its `Obj` fields must not point to a managed space other than
+    /// to an allocated part of it; the fields' targets aren't checked themselves. Statically
+    /// allocated objects (like the runtime's `the_UNIT`) are reported as unmanaged.
+    fn validate_object(&self, ptr: ObjPtr) -> Result<(), ObjDefect> {
+        let end = self
+            .allocated_end(ptr.0.cast())
+            .filter(|_| ptr.0.addr().is_multiple_of(PADDING_SIZE))
+            .ok_or(ObjDefect::Unmanaged)?;
+
+        // the header itself always fits since allocated areas are padded to `PADDING_SIZE`.
+        let tag = unsafe { ptr.try_tag() }.ok_or(ObjDefect::UnknownTag)?;
+        let field_count = unsafe { ptr.field_count() };
+
+        if tag.arity().is_some_and(|arity| arity != field_count) {
+            return Err(ObjDefect::ArityMismatch);
+        }
+
+        if unsafe { end.byte_offset_from_unsigned(ptr.0) } < unsafe { ptr.size() } {
+            return Err(ObjDefect::OutOfBounds);
+        }
+
+        for idx in 0..field_count {
+            if tag.field_kind(idx) != StellaFieldKind::Obj {
+                continue;
+            }
+
+            let field = unsafe { *ptr.field(idx) };
+
+            // pointers outside the managed spaces are fine: they refer to static objects.
+            if self.classify_space(field.0) != SpaceClass::Unmanaged
+                && (self.allocated_end(field.0.cast()).is_none()
+                    || !field.0.addr().is_multiple_of(PADDING_SIZE))
+            {
+                return Err(ObjDefect::BadField);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Formats a stella object.
+    ///
+    /// If `display_fields` is `false`, the object's fields are elided from the output.
+    ///
+    /// # Safety
+    /// The `ptr` must point to a valid stella object when [`Display::fmt`] is called.
+    unsafe fn display_obj(&self, ptr: ObjPtr, display_fields: bool) -> impl Display {
+        struct Fmt<'a> {
+            gc: &'a Gc,
+            ptr: ObjPtr,
+            display_fields: bool,
+        }
+
+        impl Display for Fmt<'_> {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let addr = self.ptr.0;
+                let space = self.gc.classify_space(addr);
+
+                let Some(tag) = (unsafe { self.ptr.try_tag() }) else {
+                    let raw_tag = unsafe { self.ptr.raw_tag() };
+
+                    // the field kinds are unknown, so the fields can't be displayed.
+                    return write!(
+                        f,
+                        "<unknown-tag:{raw_tag:#04x} @ {addr:?} ({space})> {{...}}"
+                    );
+                };
+
+                let name = tag.to_string();
+                let size = unsafe { self.ptr.size() };
+                write!(f, "<{name} @ {addr:?} ({space}, {size} B)> {{")?;
+
+                match unsafe { self.ptr.field_count() } {
+                    0 => write!(f, "}}"),
+                    _ if !self.display_fields => write!(f, "...}}"),
+
+                    field_count => {
+                        for idx in 0..field_count {
+                            if idx > 0 {
+                                write!(f, ", ")?;
+                            } else {
+                                write!(f, " ")?;
+                            }
+
+                            let field = unsafe { *self.ptr.field(idx) };
+                            let field_addr = field.0;
+                            let field_space = self.gc.classify_space(field.0.cast());
+
+                            match tag.field_kind(idx) {
+                                _ if idx == 0 && unsafe { self.gc.is_forwarded(self.ptr) } => {
+                                    write!(f, "#{field_addr:?} ({field_space}, fwd)")?
+                                }
+
+                                StellaFieldKind::Raw => match self.gc.raw_field_formatter {
+                                    Some(formatter) => {
+                                        let mut buf = [0u8; RAW_FIELD_BUF_SIZE];
+                                        formatter(
+                                            self.ptr,
+                                            idx as c_int,
+                                            buf.as_mut_ptr().cast(),
+                                            buf.len(),
+                                        );
+                                        buf[RAW_FIELD_BUF_SIZE - 1] = 0;
+
+                                        let text = CStr::from_bytes_until_nul(&buf).unwrap();
+                                        write!(f, "{}", text.to_string_lossy())?
+                                    }
+
+                                    None => write!(f, "#{field_addr:?} ({field_space})")?,
+                                },
+
+                                StellaFieldKind::Invalid => write!(
+                                    f,
+                                    "#{field_addr:?} ({field_space}, **UNEXPECTED FIELD**)",
+                                )?,
+
+                                StellaFieldKind::Obj => {
+                                    write!(f, "{}", unsafe { self.gc.display_obj(field, false) })?
+                                }
+                            }
+                        }
+
+                        write!(f, " }}")
+                    }
+                }
+            }
+        }
+
+        Fmt {
+            gc: self,
+            ptr,
+            display_fields,
+        }
+    }
+}
+
+/// A global instance of the garbage collector.
+static GC: LazyLock<Mutex<Gc>> = LazyLock::new(|| Mutex::new(unsafe { Gc::new() }));
+
+/// The NUL-terminated names of the tags (as displayed by [`StellaTag`]), indexed by their values.
+static TAG_NAMES: LazyLock<[CString; StellaTag::COUNT]> = LazyLock::new(|| {
+    std::array::from_fn(|repr| {
+        CString::new(StellaTag::from_repr(repr).unwrap().to_string()).unwrap()
+    })
+});
+
+/// Whether a GC cycle is in progress, mirrored from [`Gc::gc_in_progress`] to be readable without
+/// the lock.
+static GC_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// The number of field reads.
+///
+/// Kept outside of [`Stats`] so that [`gc_read_barrier`] can count the reads it serves without
+/// taking the lock.
+static READS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the statistics that only serve as diagnostics are being tracked, as set by
+/// [`gc_set_stats_enabled`].
+///
+/// Kept outside of [`Gc`] for the same reason as [`READS`].
+static STATS_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// Returns `true` if the diagnostic statistics are being tracked (see [`STATS_ENABLED`]).
+fn stats_enabled() -> bool {
+    STATS_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Adds a line to a report noting that the diagnostic statistics are frozen, unless they're being
+/// tracked.
+fn write_stats_disabled_note(out: &mut String) {
+    if !stats_enabled() {
+        writeln!(
+            out,
+            "  (statistics tracking is disabled; most of the counters are frozen)",
+        )
+        .unwrap();
+    }
+}
+
+/// The routine called instead of aborting on a fatal error, set by [`gc_set_panic_strategy`].
+static ESCAPE_ROUTINE: Mutex<Option<extern "C" fn() -> !>> = Mutex::new(None);
+
+/// Guards the installation of the panic hook calling [`ESCAPE_ROUTINE`].
+static ESCAPE_HOOK: Once = Once::new();
+
+/// The start of the from-space, mirrored from [`Gc::from_space`]. Only meaningful during a cycle.
+static FROM_SPACE_START: AtomicUsize = AtomicUsize::new(0);
+
+/// The end of the from-space, mirrored from [`Gc::from_space`]. Only meaningful during a cycle.
+static FROM_SPACE_END: AtomicUsize = AtomicUsize::new(0);
+
+/// Updates the lock-free mirrors of the from-space bounds used by [`gc_needs_barrier`].
+///
+/// `None` means no GC cycle is in progress.
+fn publish_from_space(from_space: Option<&Space>) {
+    if let Some(from_space) = from_space {
+        FROM_SPACE_START.store(from_space.start.addr(), Ordering::Relaxed);
+        FROM_SPACE_END.store(from_space.end().addr(), Ordering::Relaxed);
+    }
+
+    GC_IN_PROGRESS.store(from_space.is_some(), Ordering::Relaxed);
+}
+
+/// Runs `f`, which allocates memory or collects garbage, on the global GC instance.
+///
+/// Afterwards, calls the memory pressure callback if the allocation has triggered it, the post-GC
+/// callback for every GC cycle that has completed, and the finalizers of the objects that have
+/// died. The lock is released by then, so the callbacks may call into the GC.
+fn with_gc_alloc<T>(f: impl FnOnce(&mut Gc) -> T) -> T {
+    with_locked_gc_alloc(GC.lock().unwrap(), f)
+}
+
+/// Same as [`with_gc_alloc`], but uses a lock that has already been acquired.
+fn with_locked_gc_alloc<T>(mut gc: MutexGuard<'_, Gc>, f: impl FnOnce(&mut Gc) -> T) -> T {
+    let result = f(&mut gc);
+    let pressure = gc
+        .pending_pressure
+        .take()
+        .zip(gc.pressure_callback.map(|(_, cb)| cb));
+    let post_gc = gc
+        .post_gc_callback
+        .map(|cb| (mem::take(&mut gc.pending_post_gc), cb));
+    let finalizers = mem::take(&mut gc.ready_finalizers);
+    drop(gc);
+
+    if let Some(((used, total), cb)) = pressure {
+        cb(used, total);
+    }
+
+    if let Some((cycles, cb)) = post_gc {
+        for (used, total, survived, cycle) in cycles {
+            cb(used, total, survived, cycle);
+        }
+    }
+
+    for finalizer in finalizers {
+        (finalizer.cb)(finalizer.ctx);
+    }
+
+    result
+}
+
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_alloc(size_in_bytes: usize) -> *mut c_void {
+    with_gc_alloc(|gc| unsafe { gc.alloc(size_in_bytes) })
+        .0
+        .cast()
+}
+
+/// Same as `gc_alloc`, but returns null right away instead of waiting if another thread is using
+/// the GC.
+///
+/// All the GC functions serialize on a single lock, so `gc_alloc` blocks for as long as another
+/// thread holds it, which may include a whole GC increment. This function gives up instead, so a
+/// latency-sensitive thread can back off, do other work, and retry later. A null result thus means
+/// "busy", not "out of memory": running out of memory aborts the process, as with `gc_alloc`.
+/// Since the lock isn't acquired, `gc_last_error` is left unchanged in that case.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_try_alloc(size_in_bytes: usize) -> *mut c_void {
+    let gc = match GC.try_lock() {
+        Ok(gc) => gc,
+        Err(TryLockError::WouldBlock) => return ptr::null_mut(),
+        Err(TryLockError::Poisoned(e)) => panic!("{e}"),
+    };
+
+    with_locked_gc_alloc(gc, |gc| unsafe { gc.alloc(size_in_bytes) })
+        .0
+        .cast()
+}
+
+/// Same as `gc_alloc`, but the returned memory is filled with zeros.
+///
+/// The header thus reads as that of a `TAG_ZERO` object with no fields, and every field as null,
+/// until the caller sets them. Meant for objects whose fields may be left unwritten for a while;
+/// `gc_alloc` skips the zeroing and is faster when the caller initializes everything right away.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_alloc_zeroed(size_in_bytes: usize) -> *mut c_void {
+    let result = with_gc_alloc(|gc| unsafe { gc.alloc(size_in_bytes) });
+    unsafe { ptr::write_bytes(result.0.cast::<u8>(), 0, size_in_bytes) };
+
+    result.0.cast()
+}
+
+/// Allocates `size_in_bytes` bytes for a short-lived object at the high end of the heap's free area.
+///
+/// During a GC cycle, this is the same as `gc_alloc`. Otherwise, the object is placed where the
+/// objects allocated during a cycle go, instead of after the previously allocated objects. It's
+/// still an ordinary object, though: the next GC cycle copies it if it's reachable by then, so the
+/// host must keep it rooted as long as it's needed and may only rely on it being reclaimed once
+/// it's been dropped. Meant for scratch data that is dead by the time the next cycle starts. Such
+/// objects are never reclaimed by `gc_minor_collect`.
+///
+/// Falls back to `gc_alloc` if the free area can't fit the object.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_alloc_transient(size_in_bytes: usize) -> *mut c_void {
+    with_gc_alloc(|gc| unsafe { gc.alloc_transient(size_in_bytes) })
+        .0
+        .cast()
+}
+
+/// Allocates an object with the tag `tag` and `field_count` fields and initializes its header.
+///
+/// Unlike with `gc_alloc`, the object is ready to be used right away: its fields are set to null.
+/// Tuples and functions may have any number of fields that fits in the header, while other tags
+/// require a specific number of them.
+///
+/// Returns null if the tag is invalid, the number of fields doesn't match the tag (e.g., a
+/// `TAG_SUCC` object with 2 fields), or there's not enough memory while a GC cycle is in progress.
+/// The reason can be retrieved with `gc_last_error`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_alloc_tagged(tag: c_int, field_count: c_int) -> *mut c_void {
+    with_gc_alloc(|gc| {
+        let Some(tag) = usize::try_from(tag).ok().and_then(StellaTag::from_repr) else {
+            gc.set_last_error(format_args!("invalid tag: {tag}"));
+
+            return None;
+        };
+
+        let Ok(field_count) = field_count.try_into() else {
+            gc.set_last_error(format_args!("invalid field count: {field_count}"));
+
+            return None;
+        };
+
+        gc.alloc_tagged(tag, field_count)
+    })
+    .map_or(ptr::null_mut(), |obj| obj.0.cast())
+}
+
+/// Allocates an object with the tag `tag` and `count` fields initialized with the values at
+/// `fields`.
+///
+/// Unlike filling in the fields after `gc_alloc_tagged`, this never leaves a window where the
+/// object is incomplete. The values at `fields` don't need to be registered as roots: they're kept
+/// alive during the allocation, and the stored values are updated if the objects they point to are
+/// moved (the array itself is left untouched). `fields` may be null if `count` is 0.
+///
+/// Returns null under the same conditions as `gc_alloc_tagged`, or if `fields` is null while
+/// `count` isn't 0. The reason can be retrieved with `gc_last_error`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_alloc_with_fields(
+    tag: c_int,
+    count: usize,
+    fields: *const ObjPtr,
+) -> *mut c_void {
+    with_gc_alloc(|gc| {
+        let Some(tag) = usize::try_from(tag).ok().and_then(StellaTag::from_repr) else {
+            gc.set_last_error(format_args!("invalid tag: {tag}"));
+
+            return None;
+        };
+
+        let fields = if count == 0 {
+            &[][..]
+        } else if fields.is_null() {
+            gc.set_last_error(format_args!("the values of {count} fields are null"));
+
+            return None;
+        } else {
+            unsafe { slice::from_raw_parts(fields, count) }
+        };
+
+        unsafe { gc.alloc_with_fields(tag, fields) }
+    })
+    .map_or(ptr::null_mut(), |obj| obj.0.cast())
+}
+
+/// Allocates an object of at least `size_in_bytes` bytes whose address is a multiple of `align`.
+///
+/// `align` must be a power of two no greater than 64; the process is aborted otherwise. Alignments
+/// of 8 or less are equivalent to calling `gc_alloc`.
+///
+/// Stricter alignments waste up to `align - 8` bytes of padding per object, and the padding is
+/// paid again each time the object survives a collection. Over-aligned objects are also tracked in
+/// a side table, adding a lookup to the evacuation of every object while any of them are alive.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_alloc_aligned(size_in_bytes: usize, align: usize) -> *mut c_void {
+    with_gc_alloc(|gc| unsafe { gc.alloc_aligned(size_in_bytes, align) })
+        .0
+        .cast()
+}
+
+/// Reads the field `field_idx` of `obj`, forwarding it if a GC cycle is in progress.
+///
+/// Outside of a GC cycle, this doesn't take the lock: the field is read directly, and the read is
+/// counted with a relaxed atomic increment.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_read_barrier(obj: ObjPtr, field_idx: c_int) -> *mut c_void {
+    let field_idx = field_idx.try_into().unwrap();
+
+    if !GC_IN_PROGRESS.load(Ordering::Relaxed) {
+        if stats_enabled() {
+            READS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        return unsafe { *obj.field(field_idx) }.0.cast();
+    }
+
+    let result = unsafe { GC.lock().unwrap().read_barrier(obj, field_idx) };
+
+    result.0.cast()
+}
+
+/// Reads the fields `start` to `end` (exclusive) of `obj` into `out`, forwarding them if a GC cycle
+/// is in progress.
+///
+/// This is the same as calling `gc_read_barrier` for each field in the range, but the lock is only
+/// taken once. Only `Obj` fields are forwarded; the forwarded values are written back to `obj` as
+/// well. `out` must have room for `end - start` pointers. The process is aborted if `start`
+/// exceeds `end` or `end` exceeds the number of fields of `obj`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_read_barrier_range(
+    obj: ObjPtr,
+    start: usize,
+    end: usize,
+    out: *mut ObjPtr,
+) {
+    let field_count = unsafe { obj.field_count() };
+    assert!(
+        start <= end && end <= field_count,
+        "invalid field range {start}..{end} for an object with {field_count} fields",
+    );
+
+    if start == end {
+        return;
+    }
+
+    if !GC_IN_PROGRESS.load(Ordering::Relaxed) {
+        if stats_enabled() {
+            READS.fetch_add(end - start, Ordering::Relaxed);
+        }
+
+        unsafe { ptr::copy_nonoverlapping(obj.field(start), out, end - start) };
+
+        return;
+    }
+
+    let mut gc = GC.lock().unwrap();
+
+    for (idx, field_idx) in (start..end).enumerate() {
+        unsafe { out.add(idx).write(gc.read_barrier(obj, field_idx)) };
+    }
+}
+
+/// Returns 1 if `ptr` points to the from-space, and reading it therefore requires a call to
+/// `gc_read_barrier`, or 0 otherwise.
+///
+/// Unlike `gc_read_barrier`, this doesn't take the lock: outside of a GC cycle, it's a single
+/// relaxed atomic load. The result is only accurate if the GC isn't used by another thread
+/// concurrently.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_needs_barrier(ptr: ObjPtr) -> c_int {
+    if !GC_IN_PROGRESS.load(Ordering::Relaxed) {
+        return 0;
+    }
+
+    let start = FROM_SPACE_START.load(Ordering::Relaxed);
+    let end = FROM_SPACE_END.load(Ordering::Relaxed);
+
+    (start..end).contains(&ptr.0.addr()).into()
+}
+
+/// Checks whether `obj` looks like a valid GC-managed object.
+///
+/// Only the object itself is inspected, which makes this cheap enough to call whenever a pointer
+/// crosses the API boundary. Returns 0 if the object is valid, or otherwise one of the following:
+///
+/// - 1: `obj` doesn't point to an allocated part of a managed space (this includes the runtime's
+///   static objects, such as `the_UNIT`);
+/// - 2: the header holds an unknown tag;
+/// - 3: the field count doesn't match the tag's arity;
+/// - 4: the fields extend past the allocated area;
+/// - 5: an `Obj` field points to a free or misaligned location in a managed space.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_validate_object(obj: ObjPtr) -> c_int {
+    match GC.lock().unwrap().validate_object(obj) {
+        Ok(()) => 0,
+        Err(defect) => defect as c_int,
+    }
+}
+
+/// Returns the raw header word of `obj`.
+///
+/// This is a low-level primitive for tooling, such as building corrupt fixtures or patching an
+/// object in a debugging session: `obj` is neither validated nor forwarded, so a from-space
+/// pointer yields the header of the stale copy. `obj` must point to readable memory.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_object_header(obj: ObjPtr) -> Header {
+    let _gc = GC.lock().unwrap();
+
+    unsafe { (*obj.0).header }
+}
+
+/// Overwrites the raw header word of `obj` with `header`.
+///
+/// Like `gc_object_header`, this performs no validation or forwarding whatsoever. A header that
+/// doesn't describe the object's actual size or contents corrupts the heap, and the collector is
+/// likely to crash or misbehave once it gets to the object. Meant for tooling only.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_object_header(obj: ObjPtr, header: Header) {
+    let _gc = GC.lock().unwrap();
+
+    unsafe { (*obj.0).header = header };
+}
+
+/// Returns the name of the tag `tag` (such as `"cons"` for `TAG_CONS`), or null if the tag is
+/// unknown.
+///
+/// The names are the ones used in the GC's diagnostic output. The string is statically allocated
+/// and must not be freed.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_tag_name(tag: c_int) -> *const c_char {
+    usize::try_from(tag)
+        .ok()
+        .and_then(|repr| TAG_NAMES.get(repr))
+        .map_or(ptr::null(), |name| name.as_ptr())
+}
+
+/// Returns the value of the tag named `name` (as returned by `gc_tag_name`), or -1 if there's no
+/// such tag or `name` is null.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_tag_from_name(name: *const c_char) -> c_int {
+    if name.is_null() {
+        return -1;
+    }
+
+    let name = unsafe { CStr::from_ptr(name) };
+
+    TAG_NAMES
+        .iter()
+        .position(|tag_name| tag_name.as_c_str() == name)
+        .map_or(-1, |repr| repr as c_int)
+}
+
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_write_barrier(obj: ObjPtr, field_idx: c_int, value: ObjPtr) {
+    unsafe {
+        GC.lock()
+            .unwrap()
+            .record_write(obj, field_idx.try_into().unwrap(), value)
+    }
+}
+
+/// Stores `value` in the field #`field_idx` of `obj`, performing the write barrier.
+///
+/// Unlike a separate call to `gc_write_barrier`, this can't be forgotten or reordered with the
+/// store. The process is aborted if the index is out of bounds.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_field(obj: ObjPtr, field_idx: c_int, value: ObjPtr) {
+    unsafe {
+        GC.lock()
+            .unwrap()
+            .set_field(obj, field_idx.try_into().unwrap(), value)
+    }
+}
+
+/// Same as `gc_set_field`, but with the `checked-writes` feature enabled, first validates `value`
+/// if the field holds an object reference.
+///
+/// The value must be null, one of the runtime's static objects (such as `the_UNIT`), or an object
+/// that passes `gc_validate_object`. Otherwise, the process is aborted with a description of the
+/// value, which pinpoints the store that corrupted the heap rather than a collection that trips
+/// over it later. Without the feature, this is exactly `gc_set_field`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_field_checked(obj: ObjPtr, field_idx: c_int, value: ObjPtr) {
+    unsafe {
+        GC.lock()
+            .unwrap()
+            .set_field_checked(obj, field_idx.try_into().unwrap(), value)
+    }
+}
+
+/// Returns the number of fields of a tuple.
+///
+/// If `obj` isn't a tuple, returns -1 and records the reason, which can be retrieved with
+/// `gc_last_error`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_tuple_arity(obj: ObjPtr) -> c_int {
+    unsafe { GC.lock().unwrap().tuple_arity(obj) }.map_or(-1, |arity| arity.try_into().unwrap())
+}
+
+/// Reads a field of a tuple through the read barrier.
+///
+/// If `obj` isn't a tuple or `field_idx` is out of bounds, returns `NULL` and records the reason,
+/// which can be retrieved with `gc_last_error`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_tuple_field(obj: ObjPtr, field_idx: c_int) -> *mut c_void {
+    let mut gc = GC.lock().unwrap();
+
+    let Ok(field_idx) = usize::try_from(field_idx) else {
+        gc.set_last_error(format_args!("invalid field index: {field_idx}"));
+
+        return ptr::null_mut();
+    };
+
+    unsafe { gc.tuple_field(obj, field_idx) }.map_or(ptr::null_mut(), |field| field.0.cast())
+}
+
+/// Flattens a tuple, recursively inlining the elements of nested tuples.
+///
+/// Writes up to `capacity` elements to `out` and returns the total number of elements, which may
+/// exceed `capacity`. A non-tuple `obj` is treated as a tuple with a single element. Nested tuples
+/// must not form a cycle.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_tuple_flatten(obj: ObjPtr, out: *mut ObjPtr, capacity: usize) -> usize {
+    let elems = unsafe { GC.lock().unwrap().flatten_tuple(obj) };
+
+    for (idx, &elem) in elems.iter().take(capacity).enumerate() {
+        unsafe { ptr::write(out.add(idx), elem) };
+    }
+
+    elems.len()
+}
+
+/// Pushes a root onto the root stack.
+///
+/// A root may be pushed while a GC cycle is in progress, in which case its referent is forwarded
+/// right away. The slot must therefore be initialized before it's pushed.
+///
+/// Pushing the same slot more than once is allowed. Every push creates a separate entry that has to
+/// be popped on its own, and the slot stays a root until its last entry is popped. The slot is
+/// forwarded once per entry, which is harmless: forwarding a pointer that has already been
+/// forwarded leaves it unchanged. `print_gc_roots` and `print_gc_state` mark such entries as
+/// duplicates.
+///
+/// The slot itself may hold null, which is left as is, but `root` must not be null: the process is
+/// aborted otherwise. With strict mode disabled (see `gc_set_strict_roots`), a null `root` is
+/// reported and ignored instead, and so is popping it with `gc_pop_root`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_push_root(root: *mut ObjPtr) {
+    unsafe { GC.lock().unwrap().push_root(root) };
+}
+
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_pop_root(root: *mut ObjPtr) {
+    GC.lock().unwrap().pop_root(root);
+}
+
+/// Converts a string returned to the host into a NUL-terminated heap-allocated C string, which must
+/// be released with `gc_free_string`.
+fn into_c_string(s: String) -> *mut c_char {
+    CString::new(s).unwrap().into_raw()
+}
+
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn print_gc_alloc_stats() {
+    eprint!("{}", GC.lock().unwrap().alloc_stats_report());
+}
+
+/// Returns the output of `print_gc_alloc_stats` as a NUL-terminated string instead of printing it.
+///
+/// The string must be released with `gc_free_string`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_format_alloc_stats() -> *mut c_char {
+    into_c_string(GC.lock().unwrap().alloc_stats_report())
+}
+
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn print_gc_state() {
+    eprint!("{}", GC.lock().unwrap().state_report());
+}
+
+/// Returns the output of `print_gc_state` as a NUL-terminated string instead of printing it.
+///
+/// The string must be released with `gc_free_string`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_format_state() -> *mut c_char {
+    into_c_string(GC.lock().unwrap().state_report())
+}
+
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn print_gc_roots() {
+    eprint!("{}", GC.lock().unwrap().roots_report());
+}
+
+/// Returns the output of `print_gc_roots` as a NUL-terminated string instead of printing it.
+///
+/// The string must be released with `gc_free_string`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_format_roots() -> *mut c_char {
+    into_c_string(GC.lock().unwrap().roots_report())
+}
+
+/// Prints one line per root: the address of the root slot, the object it points to, the object's
+/// tag, and the memory region the object is in. Unlike `print_gc_roots`, doesn't print any fields.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_print_roots_compact() {
+    eprint!("{}", GC.lock().unwrap().roots_compact_report());
+}
+
+/// Returns the output of `gc_print_roots_compact` as a NUL-terminated string instead of printing it.
+///
+/// The string must be released with `gc_free_string`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_format_roots_compact() -> *mut c_char {
+    into_c_string(GC.lock().unwrap().roots_compact_report())
+}
+
+/// Calls `cb` for every root, passing the slot, its current value, and `ctx` along.
+///
+/// The roots are visited from the bottom of the root stack to the top. They're snapshotted under
+/// the lock before the first call to `cb`, so `cb` may call into the GC, and roots pushed or popped
+/// by `cb` don't affect the walk.
+///
+/// `cb` must not allocate, since that could trigger garbage collection and make the values passed
+/// to the remaining calls stale. This is checked after every call: if `cb` has allocated an object
+/// or caused a collection, the process is aborted with "heap mutated during iteration".
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_foreach_root(
+    cb: extern "C" fn(slot: *mut ObjPtr, referent: ObjPtr, ctx: *mut c_void),
+    ctx: *mut c_void,
+) {
+    let (roots, stamp) = {
+        let gc = GC.lock().unwrap();
+        let roots = gc
+            .roots
+            .iter()
+            .map(|&root| (root, unsafe { *root }))
+            .collect::<Vec<_>>();
+
+        (roots, gc.mutation_stamp())
+    };
+
+    for (slot, referent) in roots {
+        cb(slot, referent, ctx);
+        GC.lock().unwrap().assert_not_mutated(stamp);
+    }
+}
+
+/// Calls `cb` for every field of `obj` in order, passing the field's index, its kind, its raw value,
+/// and `ctx` along.
+///
+/// The kind is 0 for a reference to another object, 1 for an arbitrary pointer (such as the code
+/// pointer of a closure), and 2 for a field an object with this tag isn't supposed to have. This
+/// is the same classification the GC uses when tracing and printing objects; if the tag is unknown,
+/// every field is reported as kind 2. The values are read as is, without going through the read
+/// barrier.
+///
+/// The fields are snapshotted under the lock before the first call to `cb`, so `cb` may call into
+/// the GC. `cb` must not allocate, since that could trigger garbage collection and make the values
+/// passed to the remaining calls stale. This is checked after every call: if `cb` has allocated an
+/// object or caused a collection, the process is aborted with "heap mutated during iteration".
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_walk_object_fields(
+    obj: ObjPtr,
+    cb: extern "C" fn(idx: usize, kind: c_int, value: ObjPtr, ctx: *mut c_void),
+    ctx: *mut c_void,
+) {
+    let (fields, stamp) = {
+        let gc = GC.lock().unwrap();
+        let tag = unsafe { obj.try_tag() };
+        let fields = (0..unsafe { obj.field_count() })
+            .map(|idx| {
+                let kind = tag.map_or(StellaFieldKind::Invalid, |tag| tag.field_kind(idx));
+
+                (idx, kind, unsafe { *obj.field(idx) })
+            })
+            .collect::<Vec<_>>();
+
+        (fields, gc.mutation_stamp())
+    };
+
+    for (idx, kind, value) in fields {
+        cb(idx, kind as c_int, value, ctx);
+        GC.lock().unwrap().assert_not_mutated(stamp);
+    }
+}
+
+/// Prints the recorded GC events, oldest first, one per line.
+///
+/// Only the most recent 4096 events are kept.
+#[cfg(feature = "event-log")]
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_dump_event_log() {
+    eprint!("{}", GC.lock().unwrap().event_log_report());
+}
+
+/// Returns the output of `gc_dump_event_log` as a NUL-terminated string instead of printing it.
+///
+/// The string must be released with `gc_free_string`.
+#[cfg(feature = "event-log")]
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_format_event_log() -> *mut c_char {
+    into_c_string(GC.lock().unwrap().event_log_report())
+}
+
+/// Enables or disables overwriting the from-space with a poison pattern (`0xdd` bytes) at the end of
+/// every GC cycle.
+///
+/// Poisoning is enabled by default. Stale pointers into a poisoned from-space lead to objects with
+/// an invalid header, which makes use-after-collection bugs fail loudly.
+#[cfg(feature = "poison")]
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_from_space_poison(enabled: c_int) {
+    GC.lock().unwrap().poison_from_space = enabled != 0;
+}
+
+/// Returns 1 to indicate that the collector is compacting.
+///
+/// The collector copies the surviving objects next to each other, so the free memory is always
+/// contiguous, and an allocation never fails because of fragmentation.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_is_compacting() -> c_int {
+    1
+}
+
+/// Returns the largest `size_in_bytes` (header included) that `gc_alloc` could ever succeed with,
+/// given the current heap size.
+///
+/// Larger allocations are rejected without collecting garbage. The value depends on the barrier
+/// mode and may grow along with the heap.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_max_single_alloc() -> usize {
+    GC.lock().unwrap().max_single_alloc()
+}
+
+/// Returns the size of an object header in bytes, i.e., the offset of its first field.
+///
+/// Generated code should use this (or `gc_field_offset`) instead of hardcoding the object layout,
+/// which depends on how the collector is built.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_header_size() -> usize {
+    offset_of!(StellaObj, fields)
+}
+
+/// Returns the offset in bytes of the field with the index `idx` from the start of an object.
+///
+/// Accessing a field directly at this offset bypasses the barriers: during a GC cycle, a pointer
+/// read this way may still refer to the from-space, and a pointer written this way may be missed by
+/// the collector. It's thus only suitable for raw fields and for code that knows no cycle is in
+/// progress. The process is aborted if the offset doesn't fit in a `size_t`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_field_offset(idx: usize) -> usize {
+    field_offset(idx).unwrap_or_else(|| panic!("field index too large: {idx}"))
+}
+
+/// Prints how much of the to-space is used and how close the next GC cycle is.
+///
+/// If less than 25% of the to-space is consistently live after a GC cycle, the heap wastes memory;
+/// if more than 90% is, it's collected too often.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_fragmentation_report() {
+    eprint!("{}", GC.lock().unwrap().fragmentation_report());
+}
+
+/// Returns the output of `gc_fragmentation_report` as a NUL-terminated string instead of printing
+/// it.
+///
+/// The string must be released with `gc_free_string`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_format_fragmentation_report() -> *mut c_char {
+    into_c_string(GC.lock().unwrap().fragmentation_report())
+}
+
+/// Prints the number of live objects (the ones reachable from the roots, and the immortal objects)
+/// grouped into power-of-two size classes.
+///
+/// Empty size classes are omitted. Nothing but a note is printed while a GC cycle is in progress.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_print_size_histogram() {
+    eprint!("{}", unsafe { GC.lock().unwrap().size_histogram_report() });
+}
+
+/// Returns the output of `gc_print_size_histogram` as a NUL-terminated string instead of printing
+/// it.
+///
+/// The string must be released with `gc_free_string`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_format_size_histogram() -> *mut c_char {
+    into_c_string(unsafe { GC.lock().unwrap().size_histogram_report() })
+}
+
+/// Returns 1 if calling `gc_alloc(size_in_bytes)` right now would start a GC cycle, and 0 otherwise.
+///
+/// Nothing is allocated. Note that if a cycle is already in progress, this returns 0, even though
+/// the allocation would continue the cycle. The collection policy set by
+/// `gc_set_collection_policy` is taken into account.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_would_trigger_gc(size_in_bytes: usize) -> c_int {
+    GC.lock().unwrap().would_trigger_gc(size_in_bytes).into()
+}
+
+/// Allocates an object of at least `size_in_bytes` bytes in the immortal space.
+///
+/// Immortal objects are never moved or reclaimed: they stay allocated until the process exits. The
+/// GC treats their fields as roots, forwarding them at the start of every cycle, so anything they
+/// reference is kept alive as well. This makes them a good fit for values that live for the whole
+/// program, like closures of top-level functions, but each one adds to the work done when a cycle
+/// begins.
+///
+/// Never triggers garbage collection.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_alloc_immortal(size_in_bytes: usize) -> *mut c_void {
+    with_gc_alloc(|gc| gc.alloc_immortal(size_in_bytes))
+        .0
+        .cast()
+}
+
+/// Pushes `count` roots from the array `slots` onto the root stack, in order.
+///
+/// Equivalent to calling `gc_push_root` for each element of the array, but takes the lock only once.
+/// Null elements are treated the same way, so in non-strict mode they aren't pushed and must not be
+/// counted when popping the roots with `gc_pop_roots`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_push_roots(slots: *const *mut ObjPtr, count: usize) {
+    if count == 0 {
+        return;
+    }
+
+    let slots = unsafe { slice::from_raw_parts(slots, count) };
+    let mut gc = GC.lock().unwrap();
+
+    for &slot in slots {
+        unsafe { gc.push_root(slot) };
+    }
+}
+
+/// Updates each of the `count` object pointers in the array `slots` to the current address of the
+/// object it refers to.
+///
+/// If a GC cycle is in progress, pointers to the from-space are forwarded, as if each of them had
+/// been read through `gc_read_barrier`. Otherwise, the slots are left as is. Null pointers and
+/// pointers to unmanaged objects are never modified. The slots don't have to be roots, but they
+/// must not hold pointers left dangling by an earlier GC cycle.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_refresh_slots(slots: *mut ObjPtr, count: usize) {
+    if count == 0 {
+        return;
+    }
+
+    let slots = unsafe { slice::from_raw_parts_mut(slots, count) };
+    let mut gc = GC.lock().unwrap();
+
+    if !gc.gc_in_progress {
+        return;
+    }
+
+    for slot in slots {
+        *slot = unsafe { gc.forward(*slot) };
+    }
+}
+
+/// Pops `count` roots off the root stack.
+///
+/// Equivalent to calling `gc_pop_root` `count` times, but takes the lock only once.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_pop_roots(count: usize) {
+    let mut gc = GC.lock().unwrap();
+    let len = gc.roots.len();
+
+    if count > len && !gc.strict_roots {
+        eprintln!("warning: popping {count} roots from root stack of {len}, truncating to empty");
+    } else {
+        debug_assert!(
+            count <= len,
+            "popping {count} roots from a root stack of {len}",
+        );
+    }
+
+    gc.roots.truncate(len.saturating_sub(count));
+}
+
+/// Returns the total size of the GC-managed objects reachable from `obj`, including `obj` itself.
+///
+/// Shared and cyclic structure is only counted once. Must not be called while a GC cycle is in
+/// progress; the process is aborted otherwise.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_reachable_bytes(obj: ObjPtr) -> usize {
+    unsafe { GC.lock().unwrap().reachable_bytes_from(obj) }
+}
+
+/// Counts the GC-managed objects reachable from the roots without collecting or moving anything.
+///
+/// The number of objects is stored in `obj_count`, and their total size in bytes in `byte_count`;
+/// null pointers are skipped. Shared and cyclic structure is only counted once. The immortal
+/// objects are always counted, and so is everything reachable from them, since their fields act
+/// as roots (see `gc_alloc_immortal`). The heap is left untouched, so comparing the results
+/// before and after an operation is a cheap way to detect leaks. Must not be called while a GC
+/// cycle is in progress; the process is aborted otherwise.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_mark_and_count(obj_count: *mut usize, byte_count: *mut usize) {
+    let (objs, bytes) = unsafe { GC.lock().unwrap().mark_and_count() };
+
+    if !obj_count.is_null() {
+        unsafe { obj_count.write(objs) };
+    }
+
+    if !byte_count.is_null() {
+        unsafe { byte_count.write(bytes) };
+    }
+}
+
+/// Estimates how many bytes would survive a GC cycle started right now, without collecting.
+///
+/// This walks every object reachable from the roots and the immortal objects, so it's about as
+/// expensive as a full collection, minus the copying. Must not be called while a GC cycle is in
+/// progress; the process is aborted otherwise.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_estimate_live_bytes() -> usize {
+    unsafe { GC.lock().unwrap().estimate_live_after_gc() }
+}
+
+/// Returns a 64-bit digest of the live heap, suitable for detecting silent corruption.
+///
+/// The digest only depends on the structure of the object graph reachable from the roots and the
+/// immortal objects (their headers, raw field values, and which objects the fields point to), not
+/// on where the objects reside, so it stays the same across collections. Must not be called while
+/// a GC cycle is in progress; the process is aborted otherwise.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_heap_checksum() -> u64 {
+    unsafe { GC.lock().unwrap().heap_checksum() }
+}
+
+/// Runs a full GC cycle to completion, finishing the one in progress first, if any.
+///
+/// The number of bytes that survived the cycle is stored in `survived`, and the number of bytes it
+/// reclaimed in `reclaimed`; null pointers are skipped. The process is aborted if collection is
+/// suppressed by `gc_pin_cycle` or there's not enough memory for the new to-space.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_collect_and_report(survived: *mut usize, reclaimed: *mut usize) {
+    let (survived_bytes, reclaimed_bytes) = with_gc_alloc(|gc| unsafe { gc.collect() });
+
+    if !survived.is_null() {
+        unsafe { survived.write(survived_bytes) };
+    }
+
+    if !reclaimed.is_null() {
+        unsafe { reclaimed.write(reclaimed_bytes) };
+    }
+}
+
+/// Enables or disables tracking the GC statistics (enabled by default).
+///
+/// With tracking disabled, the counters that only serve as diagnostics, such as the number of
+/// reads and writes, the barrier counts, the padding and evacuation totals, and the memory usage
+/// high-water marks, are no longer updated and keep their values until tracking is re-enabled.
+/// This removes the bookkeeping from the allocation and barrier paths, e.g., for benchmarks.
+/// `print_gc_alloc_stats`, `print_gc_state`, and `gc_fragmentation_report` note that the numbers
+/// are frozen. The cycle and object counts (including the evacuated objects, which the cycle log
+/// reports), as well as the allocations since the last cycle, are still tracked, since the
+/// collector relies on them.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_stats_enabled(enabled: c_int) {
+    STATS_ENABLED.store(enabled != 0, Ordering::Relaxed);
+}
+
+/// Stores a snapshot of the GC statistics in `out`.
+///
+/// The counters reflect the whole run of the program; use `gc_stats_delta` to find out how they
+/// changed between two snapshots. The ones only serving as diagnostics stay frozen while tracking
+/// is disabled by `gc_set_stats_enabled`. Does nothing if `out` is null.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_get_stats(out: *mut GcStatsFfi) {
+    if out.is_null() {
+        return;
+    }
+
+    let stats = GC.lock().unwrap().stats_snapshot();
+    unsafe { out.write(stats) };
+}
+
+/// Stores the field-wise difference between two snapshots of the GC statistics in `out`.
+///
+/// `before` and `after` must have been filled in by `gc_get_stats`, in that order. Each field of
+/// `out` is set to how much the counter has grown in between (zero if it has decreased), so e.g.
+/// `out->gc_cycles` is the number of cycles started, and `out->allocated_objs` the number of
+/// objects allocated, between the two snapshots. `out` may alias either of the inputs. Does nothing
+/// if any of the pointers is null.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_stats_delta(
+    before: *const GcStatsFfi,
+    after: *const GcStatsFfi,
+    out: *mut GcStatsFfi,
+) {
+    if before.is_null() || after.is_null() || out.is_null() {
+        return;
+    }
+
+    let delta = unsafe { (*before).delta(&*after) };
+    unsafe { out.write(delta) };
+}
+
+/// Returns the number of bytes allocated since the last GC cycle started (or since the start of
+/// the program if there hasn't been one).
+///
+/// This includes the objects allocated during the cycle in progress, if any.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_allocated_since_gc() -> usize {
+    GC.lock().unwrap().stats.allocated_since_gc
+}
+
+/// Returns the number of objects allocated since the last GC cycle started (or since the start of
+/// the program if there hasn't been one).
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_total_allocations_since_gc() -> usize {
+    GC.lock().unwrap().stats.allocated_objs_since_gc
+}
+
+/// Runs a full GC cycle to completion, finishing the one in progress first, if any.
+///
+/// This is how garbage gets collected under the manual collection policy (see
+/// `gc_set_collection_policy`). The process is aborted if collection is suppressed by
+/// `gc_pin_cycle` or there's not enough memory for the new to-space.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_collect_now() {
+    with_gc_alloc(|gc| unsafe { gc.collect() });
+}
+
+/// Selects when allocation starts a GC cycle:
+/// - 0 (the default): once the to-space can't fit the allocation; `n` is ignored.
+/// - 1: also once `n` bytes have been allocated since the last cycle started.
+/// - 2: also once `n` objects have been allocated since the last cycle started.
+/// - 3: never. Once the to-space is full, allocation fails as if memory ran out until the host
+///   calls `gc_collect_now`. A cycle that is already in progress
+///   still advances with every allocation. `n` is ignored.
+///
+/// The process is aborted if the policy is invalid, or if `n` is 0 for policies 1 and 2.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_collection_policy(policy: c_int, n: usize) {
+    let policy = match policy {
+        0 => CollectionPolicy::WhenFull,
+        1 => CollectionPolicy::EveryNBytes(n),
+        2 => CollectionPolicy::EveryNAllocations(n),
+        3 => CollectionPolicy::Manual,
+        _ => panic!("invalid collection policy: {policy}"),
+    };
+
+    assert!(
+        !matches!(
+            policy,
+            CollectionPolicy::EveryNBytes(0) | CollectionPolicy::EveryNAllocations(0),
+        ),
+        "the collection interval must be positive",
+    );
+
+    GC.lock().unwrap().collection_policy = policy;
+}
+
+/// Enables or disables printing a one-line summary of every finished GC cycle to stderr.
+///
+/// The summary includes the cycle number, the number of roots, the number of evacuated objects, the
+/// number of bytes that survived, and the time elapsed since the cycle started (which includes the
+/// time the mutator ran in between the increments). Disabled by default.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_cycle_logging(enabled: c_int) {
+    GC.lock().unwrap().cycle_logging = enabled != 0;
+}
+
+/// Enables or disables checking the whole heap at the end of every GC cycle (disabled by default).
+///
+/// Each object in the heap is checked the same way as by `gc_validate_object`, and the process is
+/// aborted with a dump of the first object that fails the check. This walks the entire heap after
+/// every cycle, so it's only meant for debugging, e.g., to catch an object the barriers failed to
+/// keep consistent right after the cycle that broke it. Unlike the `verify-after-gc` feature, this
+/// can be turned on at run time and checks every field, not just the ones pointing to the
+/// from-space.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_verify_after_collect(enabled: c_int) {
+    GC.lock().unwrap().verify_after_collect = enabled != 0;
+}
+
+/// Sets whether popping from an empty root stack or pushing a null root slot aborts the process.
+///
+/// Strict mode is enabled by default. With strict mode disabled, `gc_pop_root` on an empty root
+/// stack prints a warning and does nothing, and `gc_pop_roots` empties the root stack instead of
+/// failing when asked to pop more roots than there are. Null slots passed to `gc_push_root` and
+/// `gc_push_roots` are reported and skipped.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_strict_roots(enabled: c_int) {
+    GC.lock().unwrap().strict_roots = enabled != 0;
+}
+
+/// Starts a new allocation epoch and returns its number.
+///
+/// Epochs are numbered sequentially starting with 1; objects allocated before the first call
+/// belong to epoch 0. Once a non-zero epoch has begun, every allocation is recorded in a side
+/// table, which makes allocation and evacuation somewhat slower.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_epoch_begin() -> u64 {
+    let mut gc = GC.lock().unwrap();
+    gc.epoch += 1;
+
+    gc.epoch
+}
+
+/// Calls `cb` for every object allocated in `epoch` or later, passing `ctx` along.
+///
+/// Only objects in the to-space and the immortal space are reported, some of which may already be
+/// unreachable. The objects are collected before the first call to `cb`, so `cb` may call into the
+/// GC without deadlocking.
+///
+/// `cb` must not allocate, since that could trigger garbage collection, which would move the
+/// remaining objects and make the pointers passed to `cb` stale. This is checked after every call:
+/// if `cb` has allocated an object or caused a collection, the process is aborted with "heap
+/// mutated during iteration".
+///
+/// Must not be called while a GC cycle is in progress; the process is aborted otherwise.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_epoch_objects(
+    epoch: u64,
+    cb: extern "C" fn(obj: ObjPtr, ctx: *mut c_void),
+    ctx: *mut c_void,
+) {
+    let (objects, stamp) = {
+        let gc = GC.lock().unwrap();
+
+        (gc.objects_since_epoch(epoch), gc.mutation_stamp())
+    };
+
+    for obj in objects {
+        cb(obj, ctx);
+        GC.lock().unwrap().assert_not_mutated(stamp);
+    }
+}
+
+/// Performs a minor collection of the objects allocated at or after `since_offset` bytes from the
+/// start of the to-space.
+///
+/// Surviving objects are compacted towards `since_offset` and afterwards treated as old: a later
+/// minor collection never looks at objects allocated before the previous one (or before the last
+/// regular GC cycle ended), whatever the offset. Older objects are left untouched and assumed to be
+/// alive, so anything they reference survives as well. For this to be sound, pointers to younger
+/// objects must only ever be stored into older objects via `gc_write_barrier`.
+///
+/// Does nothing if a GC cycle is in progress or collection is suppressed by `gc_pin_cycle`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_minor_collect(since_offset: usize) {
+    with_gc_alloc(|gc| unsafe { gc.minor_collect(since_offset) })
+}
+
+/// Creates a deep copy of the object graph reachable from `src`.
+///
+/// Every GC-managed object reachable from `src` is copied exactly once: shared substructure stays
+/// shared and cycles are preserved. Raw fields (such as function pointers in closures) and
+/// references to unmanaged objects are copied verbatim. Returns a pointer to the copy of `src`, or
+/// `src` itself if it isn't managed by the GC.
+///
+/// The copies are allocated on the GC heap, so this may start or continue a GC cycle. `src` and
+/// the objects reachable from it are kept alive for the duration of the call, but, as with any
+/// allocation, any other pointers the caller holds (including to `src`) must be registered as
+/// roots to remain valid.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_copy_object(src: ObjPtr) -> *mut c_void {
+    with_gc_alloc(|gc| unsafe { gc.copy_graph(src) }).0.cast()
+}
+
+/// Moves `obj` to the immortal space right away and returns its new address.
+///
+/// This is meant for objects the host knows to live for the rest of the program, like the
+/// top-level value of a loaded module: once immortal, an object is never copied again, but it's
+/// never reclaimed either. The objects it references are kept alive through its fields but stay
+/// where they are; use `gc_force_promote_graph` to move them as well. Every root and field
+/// referring to `obj` is updated, while the pointers held elsewhere become stale. The old copy is
+/// reclaimed by the next GC cycle.
+///
+/// Objects that aren't in the semi-spaces (immortal and unmanaged ones) are returned as is. Must
+/// not be called while a GC cycle is in progress; the process is aborted otherwise.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_force_promote(obj: ObjPtr) -> ObjPtr {
+    unsafe { GC.lock().unwrap().promote(obj, false) }
+}
+
+/// Same as `gc_force_promote`, but also moves every object reachable from `obj` that is still in
+/// the semi-spaces.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_force_promote_graph(obj: ObjPtr) -> ObjPtr {
+    unsafe { GC.lock().unwrap().promote(obj, true) }
+}
+
+/// Serializes the object graph reachable from `root` into the caller-owned buffer `out` of `cap`
+/// bytes.
+///
+/// `root` and every GC-managed object reachable from it are laid out contiguously, starting with
+/// `root` at offset 0, in their in-memory format, except that objects without fields take up just
+/// their header. `Obj` fields referring to the serialized objects are rewritten as buffer offsets
+/// with the lowest bit set. Raw fields and references to unmanaged
+/// objects (such as the statically allocated constants) are copied verbatim, so the buffer is only
+/// meaningful to a process where these pointers are valid. Over-aligned objects lose their extra
+/// alignment.
+///
+/// Returns the size of the serialized graph. If it exceeds `cap`, nothing is written, and the call
+/// can be repeated with a bigger buffer.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_copy_out(root: ObjPtr, out: *mut u8, cap: usize) -> usize {
+    let bytes = unsafe { GC.lock().unwrap().export_graph(root) };
+
+    if bytes.len() <= cap {
+        unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out, bytes.len()) };
+    }
+
+    bytes.len()
+}
+
+/// Recreates an object graph serialized by `gc_copy_out` from the `len` bytes at `buf`.
+///
+/// Returns the copy of the root. If the buffer is malformed (including when a verbatim reference
+/// points into the GC heap), or there's not enough memory, returns `NULL` and records the reason,
+/// which can be retrieved with `gc_last_error`. Like any allocation, this may start or continue a
+/// GC cycle.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_copy_in(buf: *const u8, len: usize) -> *mut c_void {
+    let buf = if len == 0 {
+        &[][..]
+    } else {
+        unsafe { slice::from_raw_parts(buf, len) }
+    };
+
+    with_gc_alloc(|gc| gc.import_graph(buf)).map_or(ptr::null_mut(), |obj| obj.0.cast())
+}
+
+/// Runs a full GC cycle to completion and returns a position-independent snapshot of the surviving
+/// heap.
+///
+/// The snapshot uses the format of `gc_copy_out`: the objects are laid out contiguously, and `Obj`
+/// fields referring to the snapshotted objects are rewritten as buffer offsets with the lowest bit
+/// set. It holds every object that survived the collection, in the order the collection laid them
+/// out, followed by the immortal objects. Unlike with `gc_copy_out`, the objects aren't
+/// necessarily reachable from the first one, so `gc_copy_in` only recreates the graph reachable
+/// from it.
+///
+/// The size of the snapshot is stored in `out_len` unless it's null. The buffer must be released
+/// with `gc_free_snapshot`. The process is aborted if collection is suppressed by `gc_pin_cycle`
+/// or there's not enough memory for the new to-space.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_collect_into_snapshot(out_len: *mut usize) -> *mut u8 {
+    let bytes = with_gc_alloc(|gc| unsafe { gc.collect_into_snapshot() });
+
+    if !out_len.is_null() {
+        unsafe { ptr::write(out_len, bytes.len()) };
+    }
+
+    Box::into_raw(bytes.into_boxed_slice()).cast()
+}
+
+/// Releases a snapshot of `len` bytes returned by `gc_collect_into_snapshot`. Does nothing if
+/// `buf` is null.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_free_snapshot(buf: *mut u8, len: usize) {
+    if !buf.is_null() {
+        drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(buf, len)) });
+    }
+}
+
+/// Replaces the collector with a fresh instance whose semi-spaces are the two caller-provided
+/// buffers of `size` bytes at `from_base` and `to_base`.
+///
+/// Since the buffers are reused for every GC cycle, object addresses become fully reproducible,
+/// which is useful for golden-output tests. The buffers must be aligned to 64 bytes, must not
+/// overlap, and must outlive every further use of the GC; `size` must be a multiple of 8.
+///
+/// All state of the previous instance is discarded, so this should be called before anything is
+/// allocated or any roots are registered.
+#[cfg(feature = "testing")]
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_use_fixed_spaces(from_base: *mut u8, to_base: *mut u8, size: usize) {
+    *GC.lock().unwrap() = unsafe { Gc::with_fixed_spaces(from_base, to_base, size) };
+}
+
+/// Sets the maximum number of roots forwarded per increment of a GC cycle, or makes every cycle
+/// forward all of them as soon as it starts if `count` is 0. Defaults to 4096.
+///
+/// With a huge root stack, forwarding it all at once is a long pause at the start of the cycle.
+/// Instead, the first `count` roots are forwarded then, and each later allocation forwards the next
+/// `count` before scanning any objects. Until a root is forwarded, the mutator may read a
+/// from-space pointer from it; the barriers redirect any access through such a pointer to the
+/// object's copy, storing it with `gc_write_barrier` evacuates its referent right away, and the
+/// objects allocated during the cycle are swept for such pointers before the cycle ends. Every root
+/// is forwarded once more at the end of the cycle.
+///
+/// Because of the sweep, an object allocated during the cycle must be fully initialized (its header
+/// and every field) before the next allocation, which may be the one to perform it.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_root_slice(count: usize) {
+    GC.lock().unwrap().root_slice = count;
+}
+
+/// Sets whether a read barrier, once triggered, forwards all object fields of the object being
+/// read rather than just the one field.
+///
+/// Disabled by default. Enabling this makes each triggered barrier more expensive, but spares
+/// subsequent reads of the other fields from triggering the barrier again.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_eager_forward(enabled: c_int) {
+    GC.lock().unwrap().eager_forward = enabled != 0;
+}
+
+/// Registers a root holding `obj` that's stored by the GC itself, and returns a handle to it.
+///
+/// Unlike with `gc_push_root`, the mutator doesn't need to keep the root in addressable memory:
+/// the GC updates its own copy whenever the object is moved, and the current value can be read
+/// back with `gc_update_value_root`. Value roots aren't part of the root stack, so they can be
+/// released in any order with `gc_remove_value_root`. The handles of released roots are reused.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_add_value_root(obj: ObjPtr) -> usize {
+    let mut gc = GC.lock().unwrap();
+
+    match gc.free_value_roots.pop() {
+        Some(handle) => {
+            gc.value_roots[handle] = Some(obj);
+
+            handle
+        }
+
+        None => {
+            gc.value_roots.push(Some(obj));
+
+            gc.value_roots.len() - 1
+        }
+    }
+}
+
+/// Returns the current value of the root `handle` returned by `gc_add_value_root`.
+///
+/// The result must be fetched again after every call that may trigger garbage collection, such as
+/// an allocation. Aborts the process if `handle` doesn't refer to a registered root.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_update_value_root(handle: usize) -> *mut c_void {
+    let mut gc = GC.lock().unwrap();
+    let Some(&Some(obj)) = gc.value_roots.get(handle) else {
+        panic!("{handle} is not a registered value root");
+    };
+
+    // the mutator must never see a from-space pointer in the Baker mode.
+    let obj = unsafe { gc.forward(obj) };
+    gc.value_roots[handle] = Some(obj);
+
+    obj.0.cast()
+}
+
+/// Releases the root `handle` returned by `gc_add_value_root`.
+///
+/// Aborts the process if `handle` doesn't refer to a registered root.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_remove_value_root(handle: usize) {
+    let mut gc = GC.lock().unwrap();
+
+    match gc.value_roots.get_mut(handle) {
+        Some(root @ Some(_)) => *root = None,
+        _ => panic!("{handle} is not a registered value root"),
+    }
+
+    gc.free_value_roots.push(handle);
+}
+
+/// Releases the memory the root stack has retained beyond its current size.
+///
+/// The root stack never shrinks on its own, so after a deep recursion it keeps the capacity needed
+/// for the peak number of roots. Does nothing if a GC cycle is in progress.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_trim_roots() {
+    GC.lock().unwrap().trim_roots();
+}
+
+/// Sets the size (in bytes) the semi-spaces grow to if the survivors of a GC cycle occupy more
+/// than half of the to-space.
+///
+/// The new size takes effect starting with the next GC cycle. The semi-spaces never shrink, and
+/// a size not exceeding the current one has no effect. Defaults to 0.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_min_heap(bytes: usize) {
+    GC.lock().unwrap().min_heap = align_down(bytes, ALIGNMENT);
+}
+
+/// Faults in the free part of the to-space by touching each of its pages once.
+///
+/// This moves the cost of the OS committing the memory out of the allocation path, which otherwise
+/// shows up as latency during the first GC cycle. The `prefault` feature does the same
+/// automatically whenever a semi-space is allocated.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_prefault() {
+    let gc = GC.lock().unwrap();
+    unsafe { prefault(gc.next, gc.limit) };
+}
+
+/// Returns the memory region `ptr` points into: 0 if it's not managed by the GC, 1 for the
+/// from-space, 2 for the to-space, or 3 for the immortal space.
+///
+/// The from-space only exists while a GC cycle is in progress. Any address within a semi-space
+/// counts, including its free part and the middle of an object, whereas only the allocated part of
+/// the immortal space does.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_space_class(ptr: *mut c_void) -> c_int {
+    match GC.lock().unwrap().classify_space(ptr.cast()) {
+        SpaceClass::Unmanaged => 0,
+        SpaceClass::From { .. } => 1,
+        SpaceClass::To { .. } => 2,
+        SpaceClass::Immortal => 3,
+    }
+}
+
+/// Stores the address ranges of the semi-spaces: `from_start..from_end` for the from-space and
+/// `to_start..to_end` for the to-space.
+///
+/// The from-space bounds are set to 0 if no GC cycle is in progress. Null output pointers are
+/// skipped. The immortal space isn't covered. The bounds stay valid until the next allocation.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_heap_bounds(
+    from_start: *mut usize,
+    from_end: *mut usize,
+    to_start: *mut usize,
+    to_end: *mut usize,
+) {
+    let gc = GC.lock().unwrap();
+    let from_bounds = gc
+        .from_space
+        .as_ref()
+        .map_or((0, 0), |space| (space.start.addr(), space.end().addr()));
+    let to_bounds = (gc.to_space.start.addr(), gc.to_space.end().addr());
+
+    for (out, value) in [
+        (from_start, from_bounds.0),
+        (from_end, from_bounds.1),
+        (to_start, to_bounds.0),
+        (to_end, to_bounds.1),
+    ] {
+        if !out.is_null() {
+            unsafe { out.write(value) };
+        }
+    }
+}
+
+/// Renders the GC-managed objects reachable from `root` as a Graphviz digraph.
+///
+/// Returns a NUL-terminated string, which must be released with `gc_free_string`. If `out_len` is
+/// not null, the length of the string (excluding the terminator) is stored there. Must not be
+/// called while a GC cycle is in progress; the process is aborted otherwise.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_dump_object_graph_dot(root: ObjPtr, out_len: *mut usize) -> *mut u8 {
+    let dot = unsafe { GC.lock().unwrap().object_graph_dot(root) };
+
+    if !out_len.is_null() {
+        unsafe { ptr::write(out_len, dot.len()) };
+    }
+
+    CString::new(dot).unwrap().into_raw().cast()
+}
+
+/// Releases a string returned by the GC. Does nothing if `s` is null.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_free_string(s: *mut u8) {
+    if !s.is_null() {
+        drop(unsafe { CString::from_raw(s.cast()) });
+    }
+}
+
+/// Reserves capacity for at least `capacity` more roots in the root stack.
+///
+/// Lets the host avoid the root stack growing (and reallocating) while the program runs. The
+/// reserved memory can be released with `gc_trim_roots`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_reserve_roots(capacity: usize) {
+    GC.lock().unwrap().roots.reserve(capacity);
+}
+
+/// Registers a callback to run when the GC is shut down with `gc_shutdown`.
+///
+/// The callbacks are run in the reverse order of registration.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_at_exit(cb: extern "C" fn()) {
+    GC.lock().unwrap().exit_callbacks.push(cb);
+}
+
+/// Tears down the GC, resetting it to the state it was in at startup.
+///
+/// First runs the callbacks registered with `gc_at_exit`, which may still use the GC. Then releases
+/// all GC-managed memory and forgets all roots and settings. Any pointers to GC-managed objects
+/// become dangling.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_shutdown() {
+    loop {
+        // the lock must be released before running the callback.
+        let Some(cb) = GC.lock().unwrap().exit_callbacks.pop() else {
+            break;
+        };
+
+        cb();
+    }
+
+    *GC.lock().unwrap() = unsafe { Gc::new() };
+}
+
+/// Chooses what happens on a fatal error, such as running out of memory or detecting heap
+/// corruption.
+///
+/// If `strategy` is 0 (the default), the error message is printed and the process is aborted. If
+/// it's 1, the message is printed and `escape` is called instead; it must not return, and is meant
+/// to `longjmp` to a recovery point set up by the host. Every fatal error is a Rust panic, so the
+/// escape routine runs from the panic hook, before any unwinding.
+///
+/// Jumping out this way is inherently unsound, and the host must accept the consequences:
+///
+/// - The Rust frames being jumped over are discarded without running destructors, so the memory
+///   they own leaks, and the GC lock may remain held. Any later call into the GC can then
+///   deadlock, so the host should treat the collector as unusable after an escape.
+/// - The GC state may be left halfway through an update, so even if the lock happens to be free,
+///   the heap can't be trusted.
+/// - The escape routine is called for panics in any thread, including ones the host has no
+///   recovery point for.
+///
+/// The strategy is therefore only suitable for reporting the failure and shutting down in a
+/// controlled way. The process is aborted if `strategy` is unknown, or if it's 1 and `escape` is
+/// null.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_panic_strategy(
+    strategy: c_int,
+    escape: Option<extern "C" fn() -> !>,
+) {
+    let escape = match strategy {
+        0 => None,
+        1 => Some(escape.expect("the escape routine is null")),
+        _ => panic!("unknown panic strategy: {strategy}"),
+    };
 
-            return Some(result);
+    ESCAPE_HOOK.call_once(|| {
+        let default_hook = panic::take_hook();
+
+        panic::set_hook(Box::new(move |info| {
+            default_hook(info);
+
+            let escape = *ESCAPE_ROUTINE.lock().unwrap_or_else(|e| e.into_inner());
+
+            if let Some(escape) = escape {
+                escape();
+            }
+        }));
+    });
+
+    *ESCAPE_ROUTINE.lock().unwrap() = escape;
+}
+
+/// Sets the share of the heap (in percent) that must be left free after a GC cycle.
+///
+/// If the objects in the heap at the end of a cycle leave less than `percent` percent of it free,
+/// the semi-spaces grow so that they would leave exactly that much, starting with the next cycle.
+/// Otherwise, a heap that is barely big enough for the live set would start a new cycle almost
+/// immediately after each one ends. The semi-spaces never shrink. 0 disables growing (the
+/// default). The process is aborted if `percent` is 100 or more.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_survivor_headroom(percent: u8) {
+    assert!(percent < 100, "invalid survivor headroom: {percent}%");
+
+    GC.lock().unwrap().survivor_headroom = percent;
+}
+
+/// Sets a callback to notify the host of memory pressure, or removes it if `cb` is null.
+///
+/// `cb` is called with the number of bytes used in the to-space and its total size the first time
+/// an allocation makes the used memory reach `threshold_percent` percent of the to-space since the
+/// last GC cycle started. The callback may call into the GC.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_pressure_callback(
+    threshold_percent: u8,
+    cb: Option<extern "C" fn(used: usize, total: usize)>,
+) {
+    let mut gc = GC.lock().unwrap();
+    gc.pressure_callback = cb.map(|cb| (threshold_percent, cb));
+    gc.pressure_latched = false;
+    gc.pending_pressure = None;
+}
+
+/// Sets a callback to notify the host once a GC cycle completes, or removes it if `cb` is null.
+///
+/// `cb` is called after every completed cycle with the number of bytes used in the to-space, its
+/// total size, the number of bytes that survived the cycle, and the cycle's number (counting from
+/// 1). The callback runs after the lock is released, so it may call into the GC.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_post_gc_callback(cb: Option<PostGcCallback>) {
+    let mut gc = GC.lock().unwrap();
+    gc.post_gc_callback = cb;
+    gc.pending_post_gc.clear();
+}
+
+/// Registers `cb` to be called with `ctx` once `obj` dies, or removes the finalizer of `obj` if `cb`
+/// is null. A new finalizer replaces the previous one.
+///
+/// Finalizers run after the allocation (or collection) that found the object unreachable, once the
+/// lock is released, so they may call into the GC. By then the object is gone, which is why the
+/// finalizer only gets `ctx`. When several objects with finalizers die together, the ones referring
+/// to others are finalized first (see `gc_set_finalization_order`). Immortal objects never die, and
+/// the finalizers still registered when the collector is shut down aren't run.
+///
+/// The process is aborted if `obj` isn't managed by the GC.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_register_finalizer(
+    obj: ObjPtr,
+    cb: Option<extern "C" fn(ctx: *mut c_void)>,
+    ctx: *mut c_void,
+) {
+    let mut gc = GC.lock().unwrap();
+    assert!(
+        gc.classify_space(obj.0) != SpaceClass::Unmanaged,
+        "cannot register a finalizer for an unmanaged object at {:?}",
+        obj.0,
+    );
+
+    match cb {
+        Some(cb) => gc.finalizers.insert(obj.0, Finalizer { cb, ctx }),
+        None => gc.finalizers.remove(&obj.0),
+    };
+}
+
+/// Sets the order in which the finalizers of objects dying together run.
+///
+/// If `referents_first` is 0 (the default), an object is finalized before the objects it refers to,
+/// directly or through other dead objects. Otherwise, the referenced objects are finalized first.
+/// Cycles are broken at an arbitrary but deterministic object, and a warning is printed.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_finalization_order(referents_first: c_int) {
+    GC.lock().unwrap().finalize_referents_first = referents_first != 0;
+}
+
+/// Suppresses garbage collection until a matching `gc_unpin_cycle` call, and returns the number of
+/// GC cycles started so far.
+///
+/// While collection is suppressed, no object is moved, so raw pointers to GC-managed objects can
+/// be cached freely. A cycle already in progress is paused rather than finished. Allocations that
+/// would need a new cycle to start fail as if the heap were out of memory, and so do those that
+/// run out of space in a paused cycle. Calls can be nested.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_pin_cycle() -> u64 {
+    let mut gc = GC.lock().unwrap();
+    gc.cycle_pins += 1;
+
+    gc.stats.gc_cycles as u64
+}
+
+/// Ends a region started by `gc_pin_cycle`, letting garbage collection resume once every such
+/// region has ended. Cached raw pointers must not be used afterwards.
+///
+/// The process is aborted if there's no matching `gc_pin_cycle` call.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_unpin_cycle() {
+    let mut gc = GC.lock().unwrap();
+    gc.cycle_pins = gc
+        .cycle_pins
+        .checked_sub(1)
+        .expect("gc_unpin_cycle called without a matching gc_pin_cycle");
+}
+
+/// Registers the range `low..high` (typically the mutator's stack) to be scanned for conservative
+/// roots at the start of every GC cycle, or disables the scan if `low` is null.
+///
+/// Every word in the range whose value points into an object in the semi-spaces keeps that object
+/// alive, in addition to the explicit roots. Since such words can't be told apart from integers and
+/// therefore can't be updated, the objects they point to are pinned: instead of being evacuated,
+/// they're moved to the immortal space together with the whole semi-space they're in, so they're
+/// never reclaimed afterwards. This makes the mode quite wasteful if conservative roots are found
+/// often. Other caveats:
+///
+/// - Pointers held only in registers aren't seen; the caller must spill them to the range (e.g.,
+///   with `setjmp`) before allocating.
+/// - The mode doesn't work with Brooks-style forwarding (see `gc_set_barrier_mode`).
+/// - The range must stay readable for as long as it's registered.
+///
+/// The process is aborted if `low` isn't null and the barrier mode is Brooks.
+#[cfg(feature = "conservative")]
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_stack_bounds(low: *const c_void, high: *const c_void) {
+    let mut gc = GC.lock().unwrap();
+
+    if low.is_null() {
+        gc.stack_bounds = None;
+    } else {
+        assert!(
+            gc.barrier_mode != BarrierMode::Brooks,
+            "conservative stack scanning is incompatible with the Brooks mode",
+        );
+
+        gc.stack_bounds = Some((low.cast(), high.cast()));
+    }
+}
+
+/// Sets the function used to render `Raw` fields (such as the code pointer of a closure) when
+/// objects are printed, or restores the default hexadecimal rendering if `formatter` is null.
+///
+/// The formatter is called with the object, the index of the field, and a buffer of the given size
+/// (64 bytes) to write a NUL-terminated string to; overlong output is truncated. It runs while the
+/// GC is locked, so it must not call back into the GC.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_raw_field_formatter(
+    formatter: Option<extern "C" fn(obj: ObjPtr, field_idx: c_int, buf: *mut c_char, len: usize)>,
+) {
+    GC.lock().unwrap().raw_field_formatter = formatter;
+}
+
+/// Makes field `field_idx` of every object tagged `tag` conditionally traced, gated on field
+/// `key_idx`, or removes the rule for `tag` if `key_idx` is negative.
+///
+/// This gives the objects ephemeron semantics: the key field doesn't keep its target alive, and the
+/// value field only does if the key's target is reachable by other means. Once a GC cycle finds
+/// the key unreachable, both fields are set to null. Only one rule can be set per tag, and it
+/// only applies to objects with enough fields to have both of them. Objects allocated while a
+/// cycle is in progress, as well as minor collections, treat both fields as ordinary ones.
+///
+/// The process is aborted if the tag is invalid, or if the fields are the same or aren't object
+/// references (e.g., the code pointer of a closure).
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_conditional_field(tag: c_int, field_idx: c_int, key_idx: c_int) {
+    let stella_tag = usize::try_from(tag)
+        .ok()
+        .and_then(StellaTag::from_repr)
+        .unwrap_or_else(|| panic!("invalid tag: {tag}"));
+    let mut gc = GC.lock().unwrap();
+
+    let Ok(key_idx) = usize::try_from(key_idx) else {
+        gc.conditional_fields[stella_tag as usize] = None;
+
+        return;
+    };
+
+    let value_idx = usize::try_from(field_idx)
+        .ok()
+        .filter(|&idx| idx != key_idx)
+        .unwrap_or_else(|| panic!("invalid conditional field index: {field_idx}"));
+
+    for idx in [value_idx, key_idx] {
+        assert!(
+            stella_tag.field_kind(idx) == StellaFieldKind::Obj,
+            "field #{idx} of an object tagged `{stella_tag}` can't hold an object reference",
+        );
+    }
+
+    gc.conditional_fields[stella_tag as usize] = Some(ConditionalField { value_idx, key_idx });
+}
+
+/// Selects how the mutator's accesses are reconciled with an ongoing GC cycle: 0 for Baker-style
+/// forwarding (the default), 1 for Brooks-style forwarding, 2 for Baker-style forwarding with a
+/// snapshot-at-the-beginning write barrier.
+///
+/// In the Brooks mode, every object gets an extra word pointing to its current copy, and the read
+/// barrier simply follows it instead of forwarding the pointer it reads. The mutator may thus get
+/// hold of from-space pointers, which stay usable until the cycle ends.
+///
+/// In the snapshot mode, `gc_write_barrier` and `gc_set_field` evacuate the object the field
+/// referred to before it's overwritten, so every object reachable when a cycle starts survives that
+/// cycle, even if it becomes garbage in the meantime. This retains more floating garbage, but the
+/// set of survivors no longer depends on how the mutator's writes interleave with the scan.
+///
+/// Since the Brooks mode lays out objects differently, the mode can only be changed before anything
+/// is allocated in the semi-spaces (immortal objects don't count). The process is aborted
+/// otherwise, or if the mode is invalid.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_barrier_mode(mode: c_int) {
+    let mode = usize::try_from(mode)
+        .ok()
+        .and_then(BarrierMode::from_repr)
+        .unwrap_or_else(|| panic!("invalid barrier mode: {mode}"));
+
+    GC.lock().unwrap().set_barrier_mode(mode);
+}
+
+/// Selects the order in which the collector evacuates objects: 0 for semi-depth-first order (the
+/// default), 1 for breadth-first order.
+///
+/// Both orders evacuate the same objects and only differ in how the survivors are laid out in the
+/// to-space. In the semi-depth-first order, an evacuated object is followed by a chain of its
+/// descendants, which keeps lists and other linked structures contiguous. In the breadth-first
+/// (Cheney) order, the objects referenced by the same object tend to end up next to each other.
+/// The order may be changed at any time, even during a GC cycle. The process is aborted if the
+/// order is invalid.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_set_scan_order(order: c_int) {
+    let order = usize::try_from(order)
+        .ok()
+        .and_then(ScanOrder::from_repr)
+        .unwrap_or_else(|| panic!("invalid scan order: {order}"));
+
+    GC.lock().unwrap().scan_order = order;
+}
+
+/// Returns a description of the most recent recoverable failure, or null if there has been none.
+///
+/// Recoverable failures are the ones reported by returning an error value, such as a null pointer
+/// from `gc_alloc_tagged`. The string stays valid until the next such failure or `gc_shutdown`.
+#[unsafe(no_mangle)]
+#[nounwind]
+pub unsafe extern "C" fn gc_last_error() -> *const c_char {
+    GC.lock()
+        .unwrap()
+        .last_error
+        .as_ref()
+        .map_or(ptr::null(), |msg| msg.as_ptr())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    #[cfg(feature = "poison")]
+    use std::cell::RefCell;
+    use std::sync::PoisonError;
+
+    use super::*;
+
+    thread_local! {
+        /// Makes [`Space::try_alloc`] fail on this thread, as if the system allocator were out of
+        /// memory.
+        pub(super) static SPACE_ALLOC_FAILS: Cell<bool> = const { Cell::new(false) };
+
+        /// A copy of the from-space taken right before it was released at the end of the last GC
+        /// cycle on this thread.
+        #[cfg(feature = "poison")]
+        pub(super) static RETIRED_FROM_SPACE: RefCell<Vec<u8>> = const { RefCell::new(vec![]) };
+    }
+
+    // the symbols the runtime provides in a real program.
+    #[cfg(not(feature = "wide-header"))]
+    #[unsafe(export_name = "FIELD_COUNT_MASK")]
+    static RUNTIME_FIELD_COUNT_MASK: Header = (1 << 8) - (1 << 4);
+    #[cfg(not(feature = "wide-header"))]
+    #[unsafe(export_name = "TAG_MASK")]
+    static RUNTIME_TAG_MASK: Header = (1 << 4) - (1 << 0);
+
+    #[cfg(feature = "wide-header")]
+    #[unsafe(export_name = "FIELD_COUNT_MASK64")]
+    static RUNTIME_FIELD_COUNT_MASK: Header = (1 << 32) - (1 << 4);
+    #[cfg(feature = "wide-header")]
+    #[unsafe(export_name = "TAG_MASK64")]
+    static RUNTIME_TAG_MASK: Header = (1 << 4) - (1 << 0);
+
+    #[unsafe(export_name = "max_alloc_size")]
+    static mut RUNTIME_MAX_ALLOC_SIZE: u64 = 64 * 1024;
+
+    /// A statically allocated stella object, like the ones defined by the runtime.
+    #[repr(transparent)]
+    struct StaticObj(StellaObj);
+
+    unsafe impl Sync for StaticObj {}
+
+    impl StaticObj {
+        const fn new(tag: StellaTag) -> Self {
+            Self(StellaObj {
+                header: tag as Header,
+                fields: [],
+            })
         }
 
-        None
+        fn ptr(&'static self) -> ObjPtr {
+            ObjPtr((&raw const self.0).cast_mut())
+        }
     }
 
-    /// Records a new allocation for the stats.
-    fn register_alloc(&mut self, size: usize) {
-        self.stats.all_time_allocated += size;
-        self.stats.all_time_allocated_objs += 1;
-        self.stats.max_used = self.stats.max_used.max(self.used_memory());
+    #[unsafe(export_name = "the_ZERO")]
+    static THE_ZERO_OBJ: StaticObj = StaticObj::new(StellaTag::Zero);
+    #[unsafe(export_name = "the_UNIT")]
+    static THE_UNIT_OBJ: StaticObj = StaticObj::new(StellaTag::Unit);
+    #[unsafe(export_name = "the_EMPTY")]
+    static THE_EMPTY_OBJ: StaticObj = StaticObj::new(StellaTag::Empty);
+    #[unsafe(export_name = "the_EMPTY_TUPLE")]
+    static THE_EMPTY_TUPLE_OBJ: StaticObj = StaticObj::new(StellaTag::Tuple);
+    #[unsafe(export_name = "the_FALSE")]
+    static THE_FALSE_OBJ: StaticObj = StaticObj::new(StellaTag::False);
+    #[unsafe(export_name = "the_TRUE")]
+    static THE_TRUE_OBJ: StaticObj = StaticObj::new(StellaTag::True);
+
+    /// Serializes the tests, which share the global GC state (such as [`GC_IN_PROGRESS`] and the
+    /// statistics toggle) even when they use their own collectors.
+    fn lock() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+
+        LOCK.lock().unwrap_or_else(PoisonError::into_inner)
     }
 
-    /// Allocates a new object of the given size.
-    ///
-    /// Starts a GC cycle if it's deemed necessary.
-    ///
-    /// # Panics
-    /// Panics if there's not enough free memory while GC is in progress.
+    /// Creates a collector with semi-spaces of `size` bytes.
+    fn new_gc(size: usize) -> Gc {
+        Gc::with_to_space(Space::alloc(size))
+    }
+
+    fn zero() -> ObjPtr {
+        THE_ZERO_OBJ.ptr()
+    }
+
+    fn empty() -> ObjPtr {
+        THE_EMPTY_OBJ.ptr()
+    }
+
+    /// Registers a new root slot holding `obj`.
     ///
-    /// # Safety
-    /// The size must be non-zero.
-    pub unsafe fn alloc(&mut self, size: usize) -> ObjPtr {
-        let size = align_up(size, ALIGNMENT);
+    /// The slot must outlive its registration.
+    fn root(gc: &mut Gc, obj: ObjPtr) -> Box<ObjPtr> {
+        let mut slot = Box::new(obj);
+        unsafe { gc.push_root(&raw mut *slot) };
 
-        if !self.gc_in_progress {
-            if let Some(result) = unsafe { self.alloc_at_next(size) } {
-                self.register_alloc(size);
+        slot
+    }
 
-                return result;
-            }
+    fn cons(gc: &mut Gc, head: ObjPtr, tail: ObjPtr) -> ObjPtr {
+        unsafe { gc.alloc_with_fields(StellaTag::Cons, &[head, tail]) }.unwrap()
+    }
 
-            unsafe { self.begin_gc() };
+    /// Builds a list of `len` zeros.
+    fn list(gc: &mut Gc, len: usize) -> ObjPtr {
+        let mut result = root(gc, empty());
+
+        for _ in 0..len {
+            *result = cons(gc, zero(), *result);
         }
 
-        if self.limit.is_null()
-            || self.next.is_null()
-            || self.limit.wrapping_byte_sub(size) < self.next
-        {
-            panic!("out of memory");
+        gc.roots.pop();
+
+        *result
+    }
+
+    /// Returns the length of a list, reading it through the read barrier.
+    fn list_len(gc: &mut Gc, mut list: ObjPtr) -> usize {
+        let mut result = 0;
+
+        while list != empty() {
+            assert_eq!(unsafe { list.tag() } as usize, StellaTag::Cons as usize);
+            list = unsafe { gc.read_barrier(list, 1) };
+            result += 1;
         }
 
-        let result = unsafe { self.limit.byte_sub(size) };
-        self.limit = result;
+        result
+    }
+
+    #[test]
+    fn collection_keeps_reachable_objects() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let live = list(&mut gc, 20);
+        let live = root(&mut gc, live);
+        list(&mut gc, 20);
 
-        unsafe { self.run_gc(size) };
-        self.register_alloc(size);
+        let (survived, reclaimed) = unsafe { gc.collect() };
 
-        ObjPtr(result.cast())
+        assert_eq!(survived, 20 * field_offset(2).unwrap());
+        assert_eq!(reclaimed, 20 * field_offset(2).unwrap());
+        assert!(gc.to_space.contains(live.0.cast()));
+        assert_eq!(list_len(&mut gc, *live), 20);
     }
 
-    /// Starts a new GC cycle.
-    ///
-    /// # Safety
-    /// This method must only be called if GC is not currently underway. All roots must have already
-    /// been registered in the root stack.
-    unsafe fn begin_gc(&mut self) {
-        self.gc_in_progress = true;
-        self.stats.gc_cycles += 1;
+    #[cfg(feature = "poison")]
+    #[test]
+    fn poison_overwrites_the_from_space() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let live = list(&mut gc, 10);
+        let live = root(&mut gc, live);
+        list(&mut gc, 10);
+
+        unsafe { gc.collect() };
+
+        RETIRED_FROM_SPACE.with_borrow(|bytes| {
+            assert_eq!(bytes.len(), 4096);
+            assert!(bytes.iter().all(|&byte| byte == POISON_BYTE));
+        });
+        assert_eq!(list_len(&mut gc, *live), 10);
+    }
 
-        let new_size = self.to_space.size;
-        mem::swap(self.from_space.get_or_insert_default(), &mut self.to_space);
+    #[test]
+    fn lenient_root_stack_underflow_is_ignored() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        gc.strict_roots = false;
+        let mut slot = Box::new(zero());
 
-        if new_size != self.to_space.size {
-            self.to_space = Space::alloc(new_size);
+        gc.pop_root(&raw mut *slot);
+        unsafe { gc.push_root(&raw mut *slot) };
+        gc.pop_root(&raw mut *slot);
+        gc.pop_root(&raw mut *slot);
+
+        assert!(gc.roots.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "popping from empty root stack")]
+    fn strict_root_stack_underflow_panics() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let mut slot = Box::new(zero());
+
+        unsafe { gc.push_root(&raw mut *slot) };
+        gc.pop_root(&raw mut *slot);
+        gc.pop_root(&raw mut *slot);
+    }
+
+    /// Runs the GC cycle in progress to completion.
+    fn finish_cycle(gc: &mut Gc) {
+        while gc.gc_in_progress {
+            unsafe { gc.run_gc(gc.to_space.size) };
         }
+    }
 
-        self.next = self.to_space.start;
-        self.scan = self.to_space.start;
-        self.limit = self.to_space.end();
+    #[test]
+    fn eager_forwarding_forwards_the_whole_object() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        gc.eager_forward = true;
 
-        let roots = mem::take(&mut self.roots);
+        let fields = [0, 1, 2].map(|_| cons(&mut gc, zero(), empty()));
+        let tuple = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &fields) }.unwrap();
+        let tuple = root(&mut gc, tuple);
+        assert!(unsafe { gc.begin_gc() });
 
-        for &root in &roots {
-            unsafe { ptr::write(root, self.forward(*root)) };
+        unsafe { gc.read_barrier(*tuple, 0) };
+
+        let copy = unsafe { gc.forward(*tuple) };
+        assert!(gc.to_space.contains(copy.0.cast()));
+
+        for idx in 0..3 {
+            let field = unsafe { *copy.field(idx) };
+            assert!(
+                gc.to_space.contains(field.0.cast()),
+                "field #{idx} not forwarded"
+            );
         }
 
-        self.roots = roots;
+        assert_eq!(gc.stats.eager_forwards, 2);
+
+        finish_cycle(&mut gc);
+
+        for idx in 0..3 {
+            let field = unsafe { gc.read_barrier(*tuple, idx) };
+            assert_eq!(list_len(&mut gc, field), 1);
+        }
     }
 
-    /// Continues the current GC cycle by scanning `n` bytes.
-    ///
-    /// # Safety
-    /// This method must only be called during a GC cycle.
-    unsafe fn run_gc(&mut self, n: usize) {
-        let target = self.scan.wrapping_byte_add(n);
+    #[test]
+    fn field_offset_overflow_is_detected() {
+        assert_eq!(field_offset(0), Some(offset_of!(StellaObj, fields)));
+        assert_eq!(field_offset(usize::MAX / FIELD_SIZE), None);
+        assert_eq!(field_offset(usize::MAX), None);
+    }
 
-        while self.scan < self.next {
-            if self.scan > target {
-                return;
-            }
+    #[test]
+    #[should_panic(expected = "overflows")]
+    fn overflowing_field_pointer_panics() {
+        unsafe { zero().field(usize::MAX / FIELD_SIZE) };
+    }
 
-            let ptr = ObjPtr(self.scan.cast());
-            let field_count = unsafe { ptr.field_count() };
+    #[test]
+    fn fabricated_header_is_caught() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let obj = unsafe { gc.alloc(field_offset(1).unwrap()) };
+        let header = StellaTag::Tuple as Header | unsafe { FIELD_COUNT_MASK };
+        unsafe { ptr::write(&raw mut (*obj.0).header, header) };
+
+        let field_count = unsafe { obj.field_count() };
+        assert_eq!(field_count, unsafe { FIELD_COUNT_MASK } as usize >> 4);
+        assert_eq!(unsafe { obj.size() }, field_offset(field_count).unwrap());
+        assert_eq!(gc.validate_object(obj), Err(ObjDefect::OutOfBounds));
+    }
 
-            for idx in 0..field_count {
-                let field_ptr = unsafe { ptr.field(idx) };
+    #[test]
+    fn trimming_roots_releases_capacity() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let mut slots = vec![zero(); 1000];
 
-                unsafe { ptr::write(field_ptr, self.forward(*field_ptr)) };
-            }
+        for slot in &mut slots {
+            unsafe { gc.push_root(slot) };
+        }
 
-            self.scan = unsafe { self.scan.byte_add(ptr.size()) };
+        for slot in slots.iter_mut().skip(10).rev() {
+            gc.pop_root(slot);
         }
 
-        self.gc_in_progress = false;
-        self.from_space = None;
+        let capacity = gc.roots.capacity();
+        gc.trim_roots();
+
+        assert!(capacity >= 1000);
+        assert!(gc.roots.capacity() < capacity);
+        assert_eq!(gc.roots.len(), 10);
     }
 
-    /// Forwards a pointer from the from-space to the to-space if necessary.
-    ///
-    /// Returns a pointer to the forwarded object, or `ptr` if forwarding is not applicable.
-    ///
-    /// # Safety
-    /// If `ptr` points to the from-space, it must point to the start of a valid stella object with
-    /// at least one field. The same requirement applies transitively to the contents of its fields.
-    unsafe fn forward(&mut self, ptr: ObjPtr) -> ObjPtr {
-        if self
-            .from_space
-            .as_ref()
-            .is_some_and(|from_space| from_space.contains(ptr.0.cast()))
-        {
-            let mut result = unsafe { *ptr.field(0) };
+    #[test]
+    fn raw_fields_are_not_forwarded() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let target = cons(&mut gc, zero(), empty());
+        let target = root(&mut gc, target);
+        let func = gc.alloc_tagged(StellaTag::Fn, 2).unwrap();
+        // the code pointer happens to hold the address of a live object.
+        let raw = ObjPtr(target.0.wrapping_byte_add(FIELD_SIZE));
+        unsafe { ptr::write(func.field(0), raw) };
+        unsafe { ptr::write(func.field(1), *target) };
+        let func = root(&mut gc, func);
+
+        assert!(unsafe { gc.begin_gc() });
+        assert!(gc.from_space.as_ref().unwrap().contains(raw.0.cast()));
+        assert_eq!(unsafe { gc.read_barrier(*func, 0) }, raw);
+
+        finish_cycle(&mut gc);
+
+        assert_eq!(unsafe { gc.read_barrier(*func, 0) }, raw);
+        assert_eq!(unsafe { gc.read_barrier(*func, 1) }, *target);
+        assert!(gc.to_space.contains(target.0.cast()));
+    }
 
-            if !self.to_space.contains(result.0.cast()) {
-                unsafe { self.chase(ptr) };
-                result = unsafe { *ptr.field(0) };
-            }
+    #[test]
+    fn ref_written_after_being_scanned_keeps_its_value_alive() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let cell = unsafe { gc.alloc_with_fields(StellaTag::Ref, &[zero()]) }.unwrap();
+        let cell = root(&mut gc, cell);
+        let other = list(&mut gc, 50);
+        let _other = root(&mut gc, other);
+        // only the mutator holds the value, so the cycle doesn't evacuate it on its own.
+        let value = list(&mut gc, 3);
+
+        assert!(unsafe { gc.begin_gc() });
+        let cell = unsafe { gc.forward(*cell) };
+        unsafe { gc.run_gc(cell.size()) };
+        assert!(gc.gc_in_progress && gc.is_scanned(cell));
+        assert!(gc.from_space.as_ref().unwrap().contains(value.0.cast()));
+
+        unsafe { gc.set_field(cell, 0, value) };
+        finish_cycle(&mut gc);
+
+        let value = unsafe { gc.read_barrier(cell, 0) };
+        assert!(gc.to_space.contains(value.0.cast()));
+        assert_eq!(list_len(&mut gc, value), 3);
+    }
 
-            assert!(self.to_space.contains(result.0.cast()));
+    #[test]
+    fn empty_object_survives_next_to_a_live_one() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let tuple = gc.alloc_tagged(StellaTag::Tuple, 0).unwrap();
+        let tuple = root(&mut gc, tuple);
+        let list = list(&mut gc, 3);
+        let list = root(&mut gc, list);
+        assert_eq!(unsafe { tuple.size() }, MIN_OBJ_SIZE);
+
+        unsafe { gc.collect() };
+
+        assert!(gc.to_space.contains(tuple.0.cast()));
+        assert_eq!(unsafe { tuple.tag() } as usize, StellaTag::Tuple as usize);
+        assert_eq!(unsafe { tuple.field_count() }, 0);
+        assert!(gc.to_space.contains(list.0.cast()));
+        assert_eq!(list_len(&mut gc, *list), 3);
+    }
 
-            result
-        } else {
-            ptr
+    /// Builds a tuple holding the same list twice and a ref pointing back to the tuple, drops a
+    /// list of garbage next to it, and checks that a collection keeps exactly the graph.
+    fn collect_shared_graph(mode: BarrierMode) {
+        let mut gc = new_gc(4096);
+        gc.set_barrier_mode(mode);
+
+        let shared = list(&mut gc, 3);
+        let cell = unsafe { gc.alloc_with_fields(StellaTag::Ref, &[zero()]) }.unwrap();
+        let tuple = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &[shared, shared, cell]) };
+        let tuple = root(&mut gc, tuple.unwrap());
+        let cell = unsafe { gc.read_barrier(*tuple, 2) };
+        unsafe { gc.set_field(cell, 0, *tuple) };
+        list(&mut gc, 5);
+
+        unsafe { gc.collect() };
+
+        let mut survivors = 0;
+        gc.for_each_object(|_| survivors += 1);
+        assert_eq!(survivors, 5);
+
+        let first = unsafe { gc.read_barrier(*tuple, 0) };
+        assert_eq!(unsafe { gc.read_barrier(*tuple, 1) }, first);
+        assert_eq!(list_len(&mut gc, first), 3);
+
+        let cell = unsafe { gc.read_barrier(*tuple, 2) };
+        assert_eq!(unsafe { gc.read_barrier(cell, 0) }, *tuple);
+    }
+
+    #[test]
+    fn baker_collects_the_shared_graph() {
+        let _lock = lock();
+        collect_shared_graph(BarrierMode::Baker);
+    }
+
+    #[test]
+    fn brooks_collects_the_shared_graph() {
+        let _lock = lock();
+        collect_shared_graph(BarrierMode::Brooks);
+    }
+
+    /// Starts a cycle with a long list to scan, pacing it at one byte scanned per byte allocated.
+    fn begin_paced_cycle(gc: &mut Gc) -> Box<ObjPtr> {
+        let list = list(gc, 2000);
+        let list = root(gc, list);
+        assert!(unsafe { gc.begin_gc() });
+        gc.stats.scan_ratio = 1.0;
+        gc.scan_debt = 0;
+
+        list
+    }
+
+    #[test]
+    fn huge_allocation_scans_a_single_slice() {
+        let _lock = lock();
+        let mut gc = new_gc(1 << 20);
+        let _list = begin_paced_cycle(&mut gc);
+        let size = 4 * MAX_SCAN_SLICE;
+        let scan = gc.scan;
+
+        let obj = unsafe { gc.alloc(size) };
+        unsafe { fill_padding(obj.0.cast(), obj.0.byte_add(size).cast()) };
+
+        let scanned = unsafe { gc.scan.byte_offset_from_unsigned(scan) };
+        assert!(gc.gc_in_progress);
+        assert!((MAX_SCAN_SLICE..MAX_SCAN_SLICE + field_offset(2).unwrap()).contains(&scanned));
+        assert_eq!(gc.scan_debt, size - scanned);
+        finish_cycle(&mut gc);
+    }
+
+    #[test]
+    fn tiny_allocations_keep_scanning_in_step() {
+        let _lock = lock();
+        let mut gc = new_gc(1 << 20);
+        let _list = begin_paced_cycle(&mut gc);
+        let size = field_offset(1).unwrap();
+        let scan = gc.scan;
+
+        for _ in 0..1000 {
+            gc.alloc_tagged(StellaTag::Tuple, 1).unwrap();
+            assert_eq!(gc.scan_debt, 0);
         }
+
+        let scanned = unsafe { gc.scan.byte_offset_from_unsigned(scan) };
+        assert!(gc.gc_in_progress);
+        assert!(scanned >= 1000 * size);
+        finish_cycle(&mut gc);
     }
 
-    /// Performs a semi-DFS walk forwarding pointers, starting with `ptr`.
-    ///
-    /// # Safety
-    /// `ptr` must point to the start of a valid stella object in the from-space with at least one
-    /// field. The same requirement applies transitively to the contents of its fields.
-    unsafe fn chase(&mut self, mut ptr: ObjPtr) {
-        loop {
-            let wr = ObjPtr(self.next.cast());
-            self.next = unsafe { self.next.wrapping_byte_add(ptr.size()) };
+    #[cfg(feature = "conservative")]
+    #[test]
+    fn stack_pins_transient_objects() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let obj = unsafe { gc.alloc_transient(field_offset(1).unwrap()) };
 
-            if self.next > self.limit {
-                panic!("out of memory");
-            }
+        unsafe {
+            ptr::write(
+                &raw mut (*obj.0).header,
+                StellaTag::Tuple as Header | 1 << 4,
+            );
+            ptr::write(obj.field(0), zero());
+        }
 
-            let mut next = ObjPtr(ptr::null_mut());
-            unsafe { ptr::copy(ptr.0, wr.0, 1) };
+        // point into the middle of the object, as a derived pointer would.
+        let stack = [unsafe { obj.field(0) }.addr()];
+        gc.stack_bounds = Some((stack.as_ptr(), stack.as_ptr().wrapping_add(1)));
+        assert!(gc.limit <= obj.0.cast());
 
-            for idx in 0..unsafe { ptr.field_count() } {
-                let field = unsafe { *ptr.field(idx) };
-                unsafe { ptr::write(wr.field(idx), field) };
+        assert!(unsafe { gc.begin_gc() });
+        assert!(gc.is_pinned(obj));
+        finish_cycle(&mut gc);
 
-                if self
-                    .from_space
-                    .as_ref()
-                    .is_some_and(|from_space| from_space.contains(field.0.cast()))
-                    && !self.to_space.contains(unsafe { *field.field(0) }.0.cast())
-                {
-                    next = field;
-                }
-            }
+        assert!(gc.immortal.contains(obj.0.cast()));
+        assert_eq!(unsafe { obj.tag() } as usize, StellaTag::Tuple as usize);
+        assert_eq!(unsafe { *obj.field(0) }, zero());
+    }
+
+    #[test]
+    fn object_with_no_fields_given_survives() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let tuple = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &[]) }.unwrap();
+        let tuple = root(&mut gc, tuple);
+        let pair = cons(&mut gc, zero(), empty());
+        let pair = root(&mut gc, pair);
+        assert_eq!(
+            unsafe { pair.0.byte_offset_from_unsigned(tuple.0) },
+            MIN_OBJ_SIZE
+        );
+
+        unsafe { gc.collect() };
+
+        assert_eq!(unsafe { tuple.field_count() }, 0);
+        assert_eq!(unsafe { gc.read_barrier(*pair, 0) }, zero());
+        assert_eq!(unsafe { gc.read_barrier(*pair, 1) }, empty());
+    }
+
+    /// Allocates and roots a tuple whose second field, a list of 3 zeros, is gated on `key`.
+    fn ephemeron(gc: &mut Gc, key: ObjPtr) -> Box<ObjPtr> {
+        gc.conditional_fields[StellaTag::Tuple as usize] = Some(ConditionalField {
+            value_idx: 1,
+            key_idx: 0,
+        });
+        let value = list(gc, 3);
+        let pair = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &[key, value]) }.unwrap();
+
+        root(gc, pair)
+    }
+
+    /// Allocates an immortal object with a single field referring to `obj`.
+    fn immortal_holder(gc: &mut Gc, obj: ObjPtr) -> ObjPtr {
+        let holder = gc.alloc_immortal(field_offset(1).unwrap());
+
+        unsafe {
+            ptr::write(
+                &raw mut (*holder.0).header,
+                StellaTag::Succ as Header | 1 << 4,
+            );
+            ptr::write(holder.field(0), obj);
+        }
+
+        holder
+    }
 
-            unsafe { ptr::write(ptr.field(0), wr) };
-            ptr = next;
+    /// Serializes the to-space and the immortal objects the way [`Gc::collect_into_snapshot`]
+    /// does, but by walking them.
+    fn walk_snapshot(gc: &Gc) -> Vec<u8> {
+        let mut objs = vec![];
+        gc.for_each_object(|ptr| objs.push(ptr));
+        gc.immortal.for_each_object(|ptr| objs.push(ptr));
 
-            if ptr.0.is_null() {
-                break;
-            }
+        let mut offsets = HashMap::new();
+        let mut result = vec![];
+
+        for &ptr in &objs {
+            let size = field_offset(unsafe { ptr.field_count() }).unwrap();
+            offsets.insert(ptr, result.len());
+            result.extend_from_slice(unsafe { slice::from_raw_parts(ptr.0.cast::<u8>(), size) });
         }
-    }
 
-    /// Reads the value of a field of a stella object, forwarding it if necessary.
-    ///
-    /// # Safety
-    /// `ptr` must point to a valid stella object. `field_idx` must be less than the field count.
-    unsafe fn read_barrier(&mut self, ptr: ObjPtr, field_idx: usize) -> ObjPtr {
-        self.stats.reads += 1;
+        for &ptr in &objs {
+            let tag = unsafe { ptr.tag() };
 
-        let mut result = unsafe { *ptr.field(field_idx) };
+            for idx in 0..unsafe { ptr.field_count() } {
+                if tag.field_kind(idx) != StellaFieldKind::Obj {
+                    continue;
+                }
 
-        if self.gc_in_progress
-            && self
-                .from_space
-                .as_ref()
-                .is_some_and(|from_space| from_space.contains(result.0.cast()))
-        {
-            unsafe {
-                result = self.forward(result);
-                ptr::write(ptr.field(field_idx), result);
+                if let Some(&target) = offsets.get(&unsafe { *ptr.field(idx) }) {
+                    let at = offsets[&ptr] + field_offset(idx).unwrap();
+                    result[at..at + FIELD_SIZE].copy_from_slice(&(target | 1).to_ne_bytes());
+                }
             }
-
-            self.stats.read_barriers += 1;
         }
 
         result
     }
 
-    /// Records a write to a field of a GC-managed object.
-    fn record_write(&mut self, ptr: ObjPtr) {
-        match self.classify_space(ptr.0) {
-            SpaceClass::From { .. } | SpaceClass::To { .. } => self.stats.writes += 1,
-            SpaceClass::Unmanaged => {}
-        }
+    fn snapshot_matches_the_heap(mode: BarrierMode) {
+        let mut gc = new_gc(4096);
+        gc.set_barrier_mode(mode);
+
+        let shared = list(&mut gc, 3);
+        let cell = unsafe { gc.alloc_with_fields(StellaTag::Ref, &[zero()]) }.unwrap();
+        let tuple = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &[shared, shared, cell]) };
+        let tuple = root(&mut gc, tuple.unwrap());
+        let cell = unsafe { gc.read_barrier(*tuple, 2) };
+        unsafe { gc.set_field(cell, 0, *tuple) };
+
+        // only reachable through the immortal object.
+        let held = list(&mut gc, 2);
+        immortal_holder(&mut gc, held);
+
+        // the value survives through its root, but the ephemeron still gets cleared.
+        let key = list(&mut gc, 2);
+        let pair = ephemeron(&mut gc, key);
+        let value = unsafe { *pair.field(1) };
+        let _value = root(&mut gc, value);
+        list(&mut gc, 5);
+
+        let snapshot = unsafe { gc.collect_into_snapshot() };
+        assert!(gc.snapshot.is_none());
+        assert!(unsafe { *pair.field(1) }.0.is_null());
+        assert_eq!(snapshot, walk_snapshot(&gc));
     }
 
-    /// Returns how much memory (in bytes) is used in the to-space.
-    fn to_space_used_memory(&self) -> usize {
-        unsafe {
-            self.to_space.end().byte_offset_from_unsigned(self.limit)
-                + self.next.byte_offset_from_unsigned(self.to_space.start)
-        }
+    #[test]
+    fn snapshot_matches_the_heap_in_the_baker_mode() {
+        let _lock = lock();
+        snapshot_matches_the_heap(BarrierMode::Baker);
     }
 
-    /// Returns how much free memory remains before the next GC cycle begins.
-    fn free_memory(&self) -> usize {
-        unsafe { self.limit.byte_offset_from_unsigned(self.next) }
+    #[test]
+    fn snapshot_matches_the_heap_in_the_brooks_mode() {
+        let _lock = lock();
+        snapshot_matches_the_heap(BarrierMode::Brooks);
     }
 
-    /// Returns how much memory is used in the both semi-spaces.
-    fn used_memory(&self) -> usize {
-        let to_space_used = self.to_space_used_memory();
+    #[test]
+    fn objects_held_by_immortal_ones_are_counted() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let held = list(&mut gc, 3);
+        let holder = immortal_holder(&mut gc, held);
+        list(&mut gc, 5);
+
+        let (objs, bytes) = unsafe { gc.mark_and_count() };
+        assert_eq!(objs, 4);
+        assert_eq!(
+            bytes,
+            unsafe { holder.size() } + 3 * field_offset(2).unwrap()
+        );
+    }
 
-        self.from_space
-            .as_ref()
-            .map(|space| space.size)
-            .unwrap_or(0)
-            + to_space_used
+    #[test]
+    fn fields_of_an_object_with_an_unknown_tag_are_read_verbatim() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        gc.eager_forward = true;
+        let value = list(&mut gc, 2);
+        let value = root(&mut gc, value);
+        let obj = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &[*value, *value]) }.unwrap();
+        unsafe { ptr::write(&raw mut (*obj.0).header, 0xf | 2 << 4) };
+        let obj = root(&mut gc, obj);
+        let stale = *value;
+
+        assert!(unsafe { gc.begin_gc() });
+        assert_eq!(unsafe { gc.read_barrier(*obj, 0) }, stale);
+        finish_cycle(&mut gc);
     }
 
-    /// Returns `true` if `ptr` has been forwarded to the to-space.
-    ///
-    /// # Safety
-    /// `ptr` must point to a valid stella object.
-    unsafe fn is_forwarded(&self, ptr: ObjPtr) -> bool {
-        let field_count = unsafe { ptr.field_count() };
+    #[test]
+    fn ephemeron_with_dead_key_is_cleared() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let key = list(&mut gc, 2);
+        let pair = ephemeron(&mut gc, key);
 
-        field_count > 0
-            && self
-                .from_space
-                .as_ref()
-                .is_some_and(|from_space| from_space.contains(ptr.0.cast()))
-            && self.to_space.contains(unsafe { *ptr.field(0) }.0.cast())
+        unsafe { gc.collect() };
+
+        assert!(unsafe { *pair.field(0) }.0.is_null());
+        assert!(unsafe { *pair.field(1) }.0.is_null());
+        assert!(gc.pending_conditional.is_empty());
     }
 
-    /// Determines the space class of the pointer.
-    fn classify_space(&self, ptr: *mut StellaObj) -> SpaceClass {
-        if let Some(from_space) = &self.from_space
-            && from_space.contains(ptr.cast())
-        {
-            SpaceClass::From {
-                offset: unsafe { ptr.byte_offset_from_unsigned(from_space.start) },
-            }
-        } else if self.to_space.contains(ptr.cast()) {
-            SpaceClass::To {
-                offset: unsafe { ptr.byte_offset_from_unsigned(self.to_space.start) },
-            }
-        } else {
-            SpaceClass::Unmanaged
-        }
+    #[test]
+    fn ephemeron_with_live_key_keeps_its_value() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let key = list(&mut gc, 2);
+        let key = root(&mut gc, key);
+        let pair = ephemeron(&mut gc, *key);
+
+        unsafe { gc.collect() };
+
+        assert_eq!(unsafe { *pair.field(0) }, *key);
+        assert_eq!(list_len(&mut gc, *key), 2);
+        let value = unsafe { *pair.field(1) };
+        assert!(gc.to_space.contains(value.0.cast()));
+        assert_eq!(list_len(&mut gc, value), 3);
     }
 
-    /// Formats a stella object.
-    ///
-    /// If `display_fields` is `false`, the object's fields are elided from the output.
-    ///
-    /// # Safety
-    /// The `ptr` must point to a valid stella object when [`Display::fmt`] is called.
-    unsafe fn display_obj(&self, ptr: ObjPtr, display_fields: bool) -> impl Display {
-        struct Fmt<'a> {
-            gc: &'a Gc,
-            ptr: ObjPtr,
-            display_fields: bool,
+    #[test]
+    fn failing_to_allocate_the_to_space_fails_the_allocation() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let size = field_offset(2).unwrap();
+
+        while !gc.would_trigger_gc(size) {
+            cons(&mut gc, zero(), empty());
         }
 
-        impl Display for Fmt<'_> {
-            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                let tag = unsafe { self.ptr.tag() };
-                let addr = self.ptr.0;
+        let next = gc.next;
+        let cycles = gc.stats.gc_cycles;
+        SPACE_ALLOC_FAILS.set(true);
+        let result = unsafe { gc.try_alloc_aligned(size, ALIGNMENT) };
+        SPACE_ALLOC_FAILS.set(false);
+
+        assert!(result.is_none());
+        assert!(
+            gc.last_error
+                .as_deref()
+                .unwrap()
+                .to_str()
+                .unwrap()
+                .starts_with("out of memory")
+        );
+        assert!(!gc.gc_in_progress);
+        assert_eq!(gc.next, next);
+        assert_eq!(gc.stats.gc_cycles, cycles);
 
-                let space = self.gc.classify_space(addr);
-                let name = tag.to_string();
-                let size = unsafe { self.ptr.size() };
-                write!(f, "<{name} @ {addr:?} ({space}, {size} B)> {{")?;
+        assert!(unsafe { gc.try_alloc_aligned(size, ALIGNMENT) }.is_some());
+        assert_eq!(gc.stats.gc_cycles, cycles + 1);
+    }
 
-                match unsafe { self.ptr.field_count() } {
-                    0 => write!(f, "}}"),
-                    _ if !self.display_fields => write!(f, "...}}"),
+    #[test]
+    fn slowly_paced_cycle_finishes_before_running_out_of_memory() {
+        let _lock = lock();
+        let mut gc = new_gc(64 * 1024);
+        let live = list(&mut gc, 1000);
+        let live = root(&mut gc, live);
 
-                    field_count => {
-                        for idx in 0..field_count {
-                            if idx > 0 {
-                                write!(f, ", ")?;
-                            } else {
-                                write!(f, " ")?;
-                            }
+        assert!(unsafe { gc.begin_gc() });
+        gc.stats.scan_ratio = SCAN_RATIO_RANGE.0;
 
-                            let field = unsafe { *self.ptr.field(idx) };
-                            let field_addr = field.0;
-                            let field_space = self.gc.classify_space(field.0.cast());
+        // the survivors would take several times the free memory to scan at this pace, so the
+        // garbage can only be reclaimed if the cycle is cut short.
+        for _ in 0..2000 {
+            gc.alloc_tagged(StellaTag::Tuple, 15).unwrap();
+        }
 
-                            match tag.field_kind(idx) {
-                                _ if idx == 0 && unsafe { self.gc.is_forwarded(self.ptr) } => {
-                                    write!(f, "#{field_addr:?} ({field_space}, fwd)")?
-                                }
+        finish_cycle(&mut gc);
+        assert_eq!(list_len(&mut gc, *live), 1000);
+    }
 
-                                StellaFieldKind::Raw => {
-                                    write!(f, "#{field_addr:?} ({field_space})")?
-                                }
+    #[test]
+    fn zeroed_allocation_reads_as_zeros() {
+        let _lock = lock();
+        const SIZE: usize = 40;
+        unsafe { gc_collect_now() };
+
+        let next = {
+            let gc = GC.lock().unwrap();
+            assert!(gc.free_memory() >= SIZE);
+            // leave some garbage where the object goes.
+            unsafe { ptr::write_bytes(gc.next, 0xa5, SIZE) };
+
+            gc.next
+        };
+
+        let obj = unsafe { gc_alloc_zeroed(SIZE) }.cast::<u8>();
+        assert_eq!(obj, next);
+        let bytes = unsafe { slice::from_raw_parts(obj, SIZE) };
+        assert!(bytes.iter().all(|&b| b == 0), "{bytes:?}");
+    }
 
-                                StellaFieldKind::Invalid => write!(
-                                    f,
-                                    "#{field_addr:?} ({field_space}, **UNEXPECTED FIELD**)",
-                                )?,
+    #[test]
+    fn zero_heap_size_is_reported() {
+        let _lock = lock();
+        unsafe { RUNTIME_MAX_ALLOC_SIZE = 0 };
+        let result = panic::catch_unwind(|| unsafe { Gc::new() });
+        unsafe { RUNTIME_MAX_ALLOC_SIZE = 64 * 1024 };
+
+        let payload = result.err().unwrap();
+        let msg = payload.downcast_ref::<String>().unwrap();
+        assert_eq!(
+            msg,
+            &format!(
+                "max_alloc_size is set to 0 B, but the heap needs at least {MIN_SPACE_SIZE} B; is \
+                 it initialized?"
+            ),
+        );
+    }
 
-                                StellaFieldKind::Obj => {
-                                    write!(f, "{}", unsafe { self.gc.display_obj(field, false) })?
-                                }
-                            }
-                        }
+    #[test]
+    fn root_pushed_during_a_cycle_is_forwarded() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let other = list(&mut gc, 50);
+        let _other = root(&mut gc, other);
+        let obj = list(&mut gc, 3);
 
-                        write!(f, " }}")
-                    }
-                }
-            }
-        }
+        assert!(unsafe { gc.begin_gc() });
+        assert!(gc.from_space.as_ref().unwrap().contains(obj.0.cast()));
+        assert!(!unsafe { gc.is_forwarded(obj) });
 
-        Fmt {
-            gc: self,
-            ptr,
-            display_fields,
+        let obj = root(&mut gc, obj);
+        assert!(gc.to_space.contains(obj.0.cast()));
+        finish_cycle(&mut gc);
+
+        assert!(gc.to_space.contains(obj.0.cast()));
+        assert_eq!(list_len(&mut gc, *obj), 3);
+    }
+
+    #[test]
+    fn object_larger_than_the_heap_is_rejected_without_collecting() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let obj = list(&mut gc, 3);
+        let _obj = root(&mut gc, obj);
+        let next = gc.next;
+
+        for size in [4096 + ALIGNMENT, usize::MAX] {
+            assert!(unsafe { gc.try_alloc_aligned(size, ALIGNMENT) }.is_none());
+            let error = gc.last_error.as_deref().unwrap().to_str().unwrap();
+            assert!(
+                error.starts_with("object too large for the heap"),
+                "{error}"
+            );
         }
+
+        assert!(!gc.gc_in_progress);
+        assert_eq!(gc.stats.gc_cycles, 0);
+        assert_eq!(gc.next, next);
     }
-}
 
-/// A global instance of the garbage collector.
-static GC: LazyLock<Mutex<Gc>> = LazyLock::new(|| Mutex::new(unsafe { Gc::new() }));
+    #[test]
+    fn object_without_fields_is_forwarded_once() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let tuple = gc.alloc_tagged(StellaTag::Tuple, 0).unwrap();
+
+        assert!(unsafe { gc.begin_gc() });
+        assert!(!unsafe { gc.is_forwarded(tuple) });
+        let copy = unsafe { gc.forward(tuple) };
+        assert!(unsafe { gc.is_forwarded(tuple) });
+        assert_eq!(unsafe { gc.forward(tuple) }, copy);
+        finish_cycle(&mut gc);
+    }
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn gc_alloc(size_in_bytes: usize) -> *mut c_void {
-    unsafe { GC.lock().unwrap().alloc(size_in_bytes) }.0.cast()
-}
+    #[test]
+    fn imported_objects_without_fields_survive() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let tuple = gc.alloc_tagged(StellaTag::Tuple, 0).unwrap();
+        let pair = cons(&mut gc, tuple, empty());
+        let pair = cons(&mut gc, tuple, pair);
+        let buf = unsafe { gc.export_graph(pair) };
+        assert_eq!(
+            buf.len(),
+            2 * field_offset(2).unwrap() + field_offset(0).unwrap()
+        );
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn gc_read_barrier(obj: ObjPtr, field_idx: c_int) -> *mut c_void {
-    let result = unsafe {
-        GC.lock()
-            .unwrap()
-            .read_barrier(obj, field_idx.try_into().unwrap())
-    };
+        let copy = gc.import_graph(&buf).unwrap();
+        let copy = root(&mut gc, copy);
+        unsafe { gc.collect() };
 
-    result.0.cast()
-}
+        let tuple = unsafe { gc.read_barrier(*copy, 0) };
+        assert_eq!(unsafe { tuple.size() }, MIN_OBJ_SIZE);
+        assert_eq!(unsafe { tuple.field_count() }, 0);
+        assert_eq!(list_len(&mut gc, *copy), 2);
+        let last = unsafe { gc.read_barrier(*copy, 1) };
+        assert_eq!(unsafe { gc.read_barrier(last, 0) }, tuple);
+    }
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn gc_write_barrier(obj: ObjPtr, _field_idx: c_int, _value: ObjPtr) {
-    GC.lock().unwrap().record_write(obj)
-}
+    #[test]
+    fn lockless_reads_are_counted() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let pair = cons(&mut gc, zero(), empty());
+        let mut out = [ObjPtr(ptr::null_mut()); 2];
+        let reads = READS.load(Ordering::Relaxed);
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn gc_push_root(root: *mut ObjPtr) {
-    GC.lock().unwrap().roots.push(root);
-}
+        for _ in 0..10 {
+            assert_eq!(unsafe { gc_read_barrier(pair, 1) }, empty().0.cast());
+        }
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn gc_pop_root(root: *mut ObjPtr) {
-    let popped = GC
-        .lock()
-        .unwrap()
-        .roots
-        .pop()
-        .expect("popping from empty root stack");
-    debug_assert_eq!(root, popped);
-}
+        unsafe { gc_read_barrier_range(pair, 0, 2, out.as_mut_ptr()) };
+        assert_eq!(out, [zero(), empty()]);
+        unsafe { gc.read_barrier(pair, 0) };
+        assert_eq!(READS.load(Ordering::Relaxed), reads + 13);
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn print_gc_alloc_stats() {
-    let gc = GC.lock().unwrap();
-    eprintln!(
-        "  - All-time allocated: {} B ({} objects)",
-        gc.stats.all_time_allocated, gc.stats.all_time_allocated_objs,
-    );
-    eprintln!("  - Used:");
-    eprintln!("    - Currently {} B", gc.used_memory());
-    eprintln!("    - Max: {} B", gc.stats.max_used);
-    eprintln!(
-        "  - GC cycles: {}{}",
-        gc.stats.gc_cycles,
-        if gc.gc_in_progress {
-            " (currently in progress)"
-        } else {
-            ""
-        },
-    );
-    eprintln!(
-        "  - Reads: {} ({} barriers)",
-        gc.stats.reads, gc.stats.read_barriers
-    );
-    eprintln!("  - Writes: {} (0 barriers)", gc.stats.writes);
-}
+        STATS_ENABLED.store(false, Ordering::Relaxed);
+        unsafe { gc_read_barrier(pair, 0) };
+        unsafe { gc_read_barrier_range(pair, 0, 2, out.as_mut_ptr()) };
+        STATS_ENABLED.store(true, Ordering::Relaxed);
+        assert_eq!(READS.load(Ordering::Relaxed), reads + 13);
+    }
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn print_gc_state() {
-    let gc = GC.lock().unwrap();
+    #[test]
+    fn reports_note_disabled_stats() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let pair = cons(&mut gc, zero(), empty());
+        let _pair = root(&mut gc, pair);
+        let note = "statistics tracking is disabled";
+
+        STATS_ENABLED.store(false, Ordering::Relaxed);
+        let evacuated_objs = gc.stats.evacuated_objs;
+        unsafe { gc.collect() };
+        let reports = [
+            gc.alloc_stats_report(),
+            gc.state_report(),
+            gc.fragmentation_report(),
+        ];
+        STATS_ENABLED.store(true, Ordering::Relaxed);
+
+        assert_eq!(gc.stats.evacuated_objs, evacuated_objs + 1);
+
+        for report in reports {
+            assert!(report.contains(note), "{report}");
+        }
 
-    eprintln!("GC state:");
+        assert!(!gc.state_report().contains(note));
+    }
 
-    if let Some(from_space) = &gc.from_space {
-        let start = from_space.start;
-        let end = from_space.end();
+    #[test]
+    fn snapshot_keeps_an_object_detached_mid_cycle() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        gc.set_barrier_mode(BarrierMode::Snapshot);
+        // only the holder itself gets evacuated when its root is forwarded.
+        gc.scan_order = ScanOrder::BreadthFirst;
+
+        let other = list(&mut gc, 50);
+        let _other = root(&mut gc, other);
+        let obj = list(&mut gc, 3);
+        let holder = unsafe { gc.alloc_with_fields(StellaTag::Ref, &[obj]) }.unwrap();
+        let holder = root(&mut gc, holder);
+
+        assert!(unsafe { gc.begin_gc() });
+        let obj = unsafe { *holder.field(0) };
+        assert!(!gc.is_scanned(*holder));
+        assert!(!unsafe { gc.is_forwarded(obj) });
+
+        unsafe { gc.set_field(*holder, 0, zero()) };
+        assert!(unsafe { gc.is_forwarded(obj) });
+        let obj = unsafe { gc.forward(obj) };
+        finish_cycle(&mut gc);
+
+        assert_eq!(unsafe { gc.read_barrier(*holder, 0) }, zero());
+        assert!(gc.to_space.contains(obj.0.cast()));
+        assert_eq!(list_len(&mut gc, obj), 3);
+    }
 
-        eprintln!("  - From-space ({start:?}..{end:?}):");
+    #[test]
+    fn value_from_a_deferred_root_written_after_scan_is_forwarded() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        gc.root_slice = 1;
+
+        let holder = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &[zero()]) }.unwrap();
+        let holder = root(&mut gc, holder);
+        let other = list(&mut gc, 50);
+        let _other = root(&mut gc, other);
+        let obj = list(&mut gc, 3);
+        let obj = root(&mut gc, obj);
+
+        assert!(unsafe { gc.begin_gc() });
+        assert!(gc.roots_deferred);
+        unsafe { gc.run_gc(holder.size()) };
+        assert!(gc.is_scanned(*holder));
+
+        // the root hasn't been forwarded yet, so this is still a from-space pointer.
+        assert!(gc.from_space.as_ref().unwrap().contains(obj.0.cast()));
+        unsafe { gc.set_field(*holder, 0, *obj) };
+        finish_cycle(&mut gc);
+
+        let value = unsafe { *holder.field(0) };
+        assert_eq!(value, *obj);
+        assert!(gc.to_space.contains(value.0.cast()));
+        assert_eq!(list_len(&mut gc, value), 3);
+    }
 
-        let mut addr = start;
+    #[test]
+    fn alignment_rounds_without_overflowing() {
+        assert_eq!(checked_align_up(0, 8), Some(0));
+        assert_eq!(checked_align_up(13, 8), Some(16));
+        assert_eq!(checked_align_up(16, 8), Some(16));
+        assert_eq!(checked_align_up(usize::MAX - 7, 8), Some(usize::MAX - 7));
+        assert_eq!(checked_align_up(usize::MAX - 6, 8), None);
+        assert_eq!(checked_align_up(usize::MAX, 1), Some(usize::MAX));
+        assert_eq!(align_down(usize::MAX, 64), usize::MAX - 63);
+    }
 
-        while addr < end {
-            let ptr = ObjPtr(addr.cast());
-            let offset = unsafe { addr.byte_offset_from_unsigned(start) };
-            eprintln!("    - {addr:?} (from-space{offset:+}): {}", unsafe {
-                gc.display_obj(ptr, true)
-            });
-            addr = unsafe { addr.byte_add(ptr.size()) };
-        }
+    #[test]
+    #[should_panic(expected = "overflows when aligned to 8")]
+    fn huge_size_overflows_align_up() {
+        align_up(usize::MAX, 8);
+    }
 
-        eprintln!();
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "invalid alignment: 0")]
+    fn zero_alignment_is_rejected() {
+        checked_align_up(8, 0);
     }
 
-    {
-        let start = gc.to_space.start;
-        let end = gc.to_space.end();
-        eprintln!("  - To-space ({start:?}..{end:?}):");
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "invalid alignment: 12")]
+    fn non_power_of_two_alignment_is_rejected() {
+        align_down(24, 12);
+    }
 
-        let mut addr = start;
+    /// Allocates pairs until the first GC cycle starts and returns how many allocations it took,
+    /// including the one that started it, and the free memory left before that allocation.
+    fn first_cycle_at(policy: CollectionPolicy) -> (usize, usize) {
+        let mut gc = new_gc(4096);
+        gc.collection_policy = policy;
 
-        while addr < gc.next {
-            let ptr = ObjPtr(addr.cast());
-            let offset = unsafe { addr.byte_offset_from_unsigned(start) };
-            eprintln!("    - {addr:?} (to-space{offset:+}): {}", unsafe {
-                gc.display_obj(ptr, true)
-            });
-            addr = unsafe { addr.byte_add(ptr.size()) };
+        for count in 1.. {
+            let free = gc.free_memory();
+            gc.alloc_tagged(StellaTag::Cons, 2).unwrap();
+
+            if gc.stats.gc_cycles > 0 {
+                return (count, free);
+            }
         }
 
-        let free_start = gc.next;
-        let free_end = gc.limit;
+        unreachable!()
+    }
 
-        if free_start < free_end {
-            eprintln!("    - {free_start:?}..{free_end:?} free");
+    /// Keeps a list filling about 90% of the heap alive while allocating garbage and returns the
+    /// number of allocations between the ends of two consecutive GC cycles, starting with the
+    /// second cycle (the first one still runs with the initial heap size).
+    fn allocations_between_cycles(headroom: u8) -> Vec<usize> {
+        let mut gc = new_gc(4096);
+        gc.survivor_headroom = headroom;
+        let live = list(&mut gc, 4096 * 9 / 10 / field_offset(2).unwrap());
+        let _live = root(&mut gc, live);
+        unsafe { gc.collect() };
+
+        let mut cycles = gc.stats.gc_cycles;
+        let mut since_last = 0;
+        let mut result = vec![];
+
+        for _ in 0..1000 {
+            gc.alloc_tagged(StellaTag::Cons, 2).unwrap();
+            since_last += 1;
+
+            if gc.stats.gc_cycles > cycles {
+                cycles = gc.stats.gc_cycles;
+                result.push(since_last);
+                since_last = 0;
+            }
         }
 
-        addr = gc.limit;
+        result.remove(0);
 
-        while addr < end {
-            let ptr = ObjPtr(addr.cast());
-            let offset = unsafe { addr.byte_offset_from_unsigned(start) };
-            eprintln!("    - {addr:?} (to-space{offset:+}): {}", unsafe {
-                gc.display_obj(ptr, true)
-            });
-            addr = unsafe { addr.byte_add(ptr.size()) };
-        }
+        result
     }
 
-    eprintln!();
+    #[test]
+    fn survivor_headroom_spaces_out_cycles() {
+        let _lock = lock();
+        // a quarter of the initial heap.
+        let headroom = 4096 / 4 / field_offset(2).unwrap();
 
-    if gc.gc_in_progress {
-        eprintln!("  - Garbage collection currently in progress:");
-        eprintln!("    - Scan pointer: {:?}", gc.scan);
-        eprintln!("    - Next pointer: {:?}", gc.next);
-        eprintln!("    - Limit pointer: {:?}", gc.limit);
-    } else {
-        eprintln!("  - Garbage collection currently not running");
+        let thrashing = allocations_between_cycles(0);
+        assert!(
+            thrashing.iter().all(|&count| count < headroom),
+            "{thrashing:?}"
+        );
+
+        let spaced_out = allocations_between_cycles(25);
+        assert!(!spaced_out.is_empty());
+        assert!(
+            spaced_out.iter().all(|&count| count >= headroom),
+            "{spaced_out:?}"
+        );
     }
 
-    eprintln!();
+    #[test]
+    fn when_full_policy_collects_once_the_heap_is_full() {
+        let _lock = lock();
+        let size = field_offset(2).unwrap();
+        let (count, free) = first_cycle_at(CollectionPolicy::WhenFull);
+        assert!(free <= size);
+        assert_eq!(count, 4096 / size + 1);
+    }
 
-    if gc.roots.is_empty() {
-        eprintln!("  - Roots: (none)");
-    } else {
-        eprintln!("  - Roots:");
+    #[test]
+    fn byte_policy_collects_past_the_threshold() {
+        let _lock = lock();
+        let size = field_offset(2).unwrap();
+        assert_eq!(
+            first_cycle_at(CollectionPolicy::EveryNBytes(10 * size)).0,
+            11
+        );
+        assert_eq!(
+            first_cycle_at(CollectionPolicy::EveryNBytes(10 * size - 1)).0,
+            10
+        );
+    }
 
-        for &root in &gc.roots {
-            let addr = unsafe { *root }.0;
+    #[test]
+    fn allocation_count_policy_collects_past_the_threshold() {
+        let _lock = lock();
+        assert_eq!(first_cycle_at(CollectionPolicy::EveryNAllocations(5)).0, 6);
+    }
 
-            if gc.classify_space(addr.cast()) == SpaceClass::Unmanaged {
-                eprintln!("    - **ILLEGAL** {root:?} points to {addr:?} (**unmanaged memory**)");
-            } else {
-                eprintln!("    - {root:?} points to {}", unsafe {
-                    gc.display_obj(*root, true)
-                });
-            }
+    #[test]
+    fn manual_policy_never_collects_on_allocation() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        gc.collection_policy = CollectionPolicy::Manual;
+
+        while gc.alloc_tagged(StellaTag::Cons, 2).is_some() {}
+
+        assert_eq!(gc.stats.gc_cycles, 0);
+        let error = gc.last_error.as_deref().unwrap().to_str().unwrap();
+        assert!(error.ends_with("until garbage is collected"), "{error}");
+
+        unsafe { gc.collect() };
+        assert!(gc.alloc_tagged(StellaTag::Cons, 2).is_some());
+    }
+
+    #[test]
+    fn minor_collection_treats_transient_objects_as_roots() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        list(&mut gc, 5);
+        let young = list(&mut gc, 3);
+        let obj = unsafe { gc.alloc_transient(field_offset(1).unwrap()) };
+
+        unsafe {
+            ptr::write(
+                &raw mut (*obj.0).header,
+                StellaTag::Tuple as Header | 1 << 4,
+            );
+            ptr::write(obj.field(0), young);
         }
+
+        unsafe { gc.minor_collect(0) };
+
+        let moved = unsafe { *obj.field(0) };
+        assert_ne!(moved, young);
+        assert!((gc.to_space.start..gc.next).contains(&moved.0.cast()));
+        assert_eq!(list_len(&mut gc, moved), 3);
     }
 
-    eprintln!();
-    eprintln!("  - Currently used: {} B", gc.used_memory());
+    #[test]
+    fn root_holding_null_is_kept() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        let obj = root(&mut gc, ObjPtr(ptr::null_mut()));
 
-    if let Some(from_space) = &gc.from_space {
-        eprintln!(
-            "    - From-space: {} B / {} B used, 0 B free",
-            from_space.size, from_space.size,
-        );
+        unsafe { gc.collect() };
+        assert_eq!(gc.roots.len(), 1);
+        assert!(obj.0.is_null());
+
+        assert!(unsafe { gc.begin_gc() });
+        let _other = root(&mut gc, ObjPtr(ptr::null_mut()));
+        finish_cycle(&mut gc);
+        assert_eq!(gc.roots.len(), 2);
     }
 
-    eprintln!(
-        "    - To-space: {} B / {} B used, {} B free",
-        gc.to_space_used_memory(),
-        gc.to_space.size,
-        gc.free_memory(),
-    );
+    #[test]
+    fn lenient_null_root_slot_is_ignored() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        gc.strict_roots = false;
 
-    eprintln!();
-}
+        unsafe { gc.push_root(ptr::null_mut()) };
+        assert!(gc.roots.is_empty());
+        gc.pop_root(ptr::null_mut());
+        assert!(gc.roots.is_empty());
+    }
 
-#[unsafe(no_mangle)]
-#[nounwind]
-pub unsafe extern "C" fn print_gc_roots() {
-    let gc = GC.lock().unwrap();
+    #[test]
+    #[should_panic(expected = "pushing a null root slot")]
+    fn strict_null_root_slot_panics() {
+        let _lock = lock();
+        let mut gc = new_gc(4096);
+        unsafe { gc.push_root(ptr::null_mut()) };
+    }
 
-    for &root in &gc.roots {
-        let addr = unsafe { *root }.0;
+    /// Describes the graph reachable from `root` independently of where the objects are: each
+    /// object, in the order of discovery, is listed with its tag and fields, with the fields
+    /// pointing to the to-space replaced by the indices of their targets.
+    fn shape(gc: &Gc, root: ObjPtr) -> Vec<(usize, Vec<usize>)> {
+        let mut objs = vec![root];
+        let mut result = vec![];
 
-        if gc.classify_space(addr.cast()) == SpaceClass::Unmanaged {
-            eprintln!("**ILLEGAL** {root:?} points to {addr:?} (**unmanaged memory**)");
-        } else {
-            eprintln!("{root:?} points to {}", unsafe {
-                gc.display_obj(*root, true)
-            });
+        while let Some(&ptr) = objs.get(result.len()) {
+            let fields = (0..unsafe { ptr.field_count() })
+                .map(|idx| {
+                    let field = unsafe { *ptr.field(idx) };
+
+                    if !gc.to_space.contains(field.0.cast()) {
+                        return field.0.addr();
+                    }
+
+                    objs.iter()
+                        .position(|&obj| obj == field)
+                        .unwrap_or_else(|| {
+                            objs.push(field);
+
+                            objs.len() - 1
+                        })
+                })
+                .collect();
+
+            result.push((unsafe { ptr.raw_tag() }, fields));
         }
+
+        result
+    }
+
+    /// Collects a graph with sharing and a cycle in the given scan order, returning the number of
+    /// survivors and the shape of the graph.
+    fn collect_in_order(order: ScanOrder) -> (usize, Vec<(usize, Vec<usize>)>) {
+        let mut gc = new_gc(4096);
+        gc.scan_order = order;
+
+        let shared = list(&mut gc, 4);
+        let cell = unsafe { gc.alloc_with_fields(StellaTag::Ref, &[zero()]) }.unwrap();
+        let left = cons(&mut gc, shared, empty());
+        let right = cons(&mut gc, zero(), shared);
+        list(&mut gc, 7);
+        let tuple = unsafe { gc.alloc_with_fields(StellaTag::Tuple, &[left, right, cell]) };
+        let tuple = root(&mut gc, tuple.unwrap());
+        let cell = unsafe { gc.read_barrier(*tuple, 2) };
+        unsafe { gc.set_field(cell, 0, *tuple) };
+        list(&mut gc, 3);
+
+        unsafe { gc.collect() };
+
+        let mut survivors = 0;
+        gc.for_each_object(|_| survivors += 1);
+
+        (survivors, shape(&gc, *tuple))
+    }
+
+    #[test]
+    fn scan_orders_keep_the_same_objects() {
+        let _lock = lock();
+        let depth_first = collect_in_order(ScanOrder::DepthFirst);
+        let breadth_first = collect_in_order(ScanOrder::BreadthFirst);
+
+        assert_eq!(depth_first.0, 8);
+        assert_eq!(depth_first, breadth_first);
     }
 }
@@ -3,6 +3,7 @@
 
 #include <stdlib.h>
 #include <stdio.h>
+#include <stdint.h>
 
 /** This macro is used whenever the runtime wants to READ a heap object's field.
  */
@@ -27,6 +28,7 @@ void *gc_read_barrier(void *object, int field_index);
 void gc_write_barrier(void *object, int field_index, void *contents);
 
 /** Push a reference to a root (variable) on the GC's stack of roots.
+ * The variable must be initialized first.
  */
 void gc_push_root(void **object);
 /** Pop a reference to a root (variable) on the GC's stack of roots.
@@ -58,4 +60,480 @@ void print_gc_state();
  */
 void print_gc_roots();
 
+/** The type of an object header as seen by `gc_object_header`.
+ * Define GC_WIDE_HEADER when the collector is built with the `wide-header` feature.
+ */
+#ifdef GC_WIDE_HEADER
+typedef int64_t gc_header;
+#else
+typedef int gc_header;
+#endif
+
+/** A snapshot of the GC statistics (see `gc_get_stats`).
+ */
+typedef struct GcStatsFfi {
+  /** The number of field reads through `gc_read_barrier`. */
+  size_t reads;
+  /** The number of field writes. */
+  size_t writes;
+  /** The number of field reads that triggered a read barrier. */
+  size_t read_barriers;
+  /** The number of field writes that evacuated the overwritten value in the snapshot mode. */
+  size_t write_barriers;
+  /** The number of fields forwarded eagerly by a read barrier. */
+  size_t eager_forwards;
+  /** The amount of memory allocated since the start of the program. */
+  size_t allocated_bytes;
+  /** The number of objects allocated since the start of the program. */
+  size_t allocated_objs;
+  /** The amount of memory the collector has copied surviving objects to. */
+  size_t evacuated_bytes;
+  /** The number of objects the collector has copied to the to-space. */
+  size_t evacuated_objs;
+  /** The amount of memory the objects promoted to the immortal space occupy. */
+  size_t promoted_bytes;
+  /** The number of objects promoted to the immortal space. */
+  size_t promoted_objs;
+  /** The number of bytes lost to alignment. */
+  size_t padding_bytes;
+  /** The number of GC cycles, including the one in progress, if any. */
+  size_t gc_cycles;
+  /** The number of minor collections. */
+  size_t minor_cycles;
+  /** The maximum amount of used memory managed by the GC. */
+  size_t max_used;
+  /** The maximum amount of memory used in the to-space. */
+  size_t max_to_space_used;
+} GcStatsFfi;
+
+/** Same as `gc_alloc`, but returns null right away instead of waiting if another thread is using
+ * the GC.
+ */
+void *gc_try_alloc(size_t size_in_bytes);
+
+/** Same as `gc_alloc`, but the returned memory is filled with zeros.
+ */
+void *gc_alloc_zeroed(size_t size_in_bytes);
+
+/** Allocates `size_in_bytes` bytes for a short-lived object at the high end of the heap's free
+ * area.
+ */
+void *gc_alloc_transient(size_t size_in_bytes);
+
+/** Allocates an object with the tag `tag` and `field_count` fields and initializes its header.
+ */
+void *gc_alloc_tagged(int tag, int field_count);
+
+/** Allocates an object with the tag `tag` and `count` fields initialized with the values at
+ * `fields`.
+ */
+void *gc_alloc_with_fields(int tag, size_t count, void *const *fields);
+
+/** Allocates an object of at least `size_in_bytes` bytes whose address is a multiple of `align`.
+ */
+void *gc_alloc_aligned(size_t size_in_bytes, size_t align);
+
+/** Reads the fields `start` to `end` (exclusive) of `obj` into `out`, forwarding them if a GC cycle
+ * is in progress.
+ */
+void gc_read_barrier_range(void *obj, size_t start, size_t end, void **out);
+
+/** Returns 1 if `ptr` points to the from-space, and reading it therefore requires a call to
+ * `gc_read_barrier`, or 0 otherwise.
+ */
+int gc_needs_barrier(void *ptr);
+
+/** Checks whether `obj` looks like a valid GC-managed object.
+ */
+int gc_validate_object(void *obj);
+
+/** Returns the raw header word of `obj`.
+ */
+gc_header gc_object_header(void *obj);
+
+/** Overwrites the raw header word of `obj` with `header`.
+ */
+void gc_set_object_header(void *obj, gc_header header);
+
+/** Returns the name of the tag `tag` (such as `"cons"` for `TAG_CONS`), or null if the tag is
+ * unknown.
+ */
+const char *gc_tag_name(int tag);
+
+/** Returns the value of the tag named `name` (as returned by `gc_tag_name`), or -1 if there's no
+ * such tag or `name` is null.
+ */
+int gc_tag_from_name(const char *name);
+
+/** Stores `value` in the field #`field_idx` of `obj`, performing the write barrier.
+ */
+void gc_set_field(void *obj, int field_idx, void *value);
+
+/** Same as `gc_set_field`, but with the `checked-writes` feature enabled, first validates `value`
+ * if the field holds an object reference.
+ */
+void gc_set_field_checked(void *obj, int field_idx, void *value);
+
+/** Returns the number of fields of a tuple.
+ */
+int gc_tuple_arity(void *obj);
+
+/** Reads a field of a tuple through the read barrier.
+ */
+void *gc_tuple_field(void *obj, int field_idx);
+
+/** Flattens a tuple, recursively inlining the elements of nested tuples.
+ */
+size_t gc_tuple_flatten(void *obj, void **out, size_t capacity);
+
+/** Returns the output of `print_gc_alloc_stats` as a NUL-terminated string instead of printing it.
+ */
+char *gc_format_alloc_stats(void);
+
+/** Returns the output of `print_gc_state` as a NUL-terminated string instead of printing it.
+ */
+char *gc_format_state(void);
+
+/** Returns the output of `print_gc_roots` as a NUL-terminated string instead of printing it.
+ */
+char *gc_format_roots(void);
+
+/** Prints one line per root: the address of the root slot, the object it points to, the object's
+ * tag, and the memory region the object is in.
+ */
+void gc_print_roots_compact(void);
+
+/** Returns the output of `gc_print_roots_compact` as a NUL-terminated string instead of printing
+ * it.
+ */
+char *gc_format_roots_compact(void);
+
+/** Calls `cb` for every root, passing the slot, its current value, and `ctx` along.
+ */
+void gc_foreach_root(void (*cb)(void **slot, void *referent, void *ctx), void *ctx);
+
+/** Calls `cb` for every field of `obj` in order, passing the field's index, its kind, its raw
+ * value, and `ctx` along.
+ */
+void gc_walk_object_fields(void *obj, void (*cb)(size_t idx, int kind, void *value, void *ctx), void *ctx);
+
+/** Prints the recorded GC events, oldest first, one per line.
+ * Only available with the `event-log` feature.
+ */
+void gc_dump_event_log(void);
+
+/** Returns the output of `gc_dump_event_log` as a NUL-terminated string instead of printing it.
+ * Only available with the `event-log` feature.
+ */
+char *gc_format_event_log(void);
+
+/** Enables or disables overwriting the from-space with a poison pattern (`0xdd` bytes) at the end
+ * of every GC cycle.
+ * Only available with the `poison` feature.
+ */
+void gc_set_from_space_poison(int enabled);
+
+/** Returns 1 to indicate that the collector is compacting.
+ */
+int gc_is_compacting(void);
+
+/** Returns the largest `size_in_bytes` (header included) that `gc_alloc` could ever succeed with,
+ * given the current heap size.
+ */
+size_t gc_max_single_alloc(void);
+
+/** Returns the size of an object header in bytes, i.e., the offset of its first field.
+ */
+size_t gc_header_size(void);
+
+/** Returns the offset in bytes of the field with the index `idx` from the start of an object.
+ */
+size_t gc_field_offset(size_t idx);
+
+/** Prints how much of the to-space is used and how close the next GC cycle is.
+ */
+void gc_fragmentation_report(void);
+
+/** Returns the output of `gc_fragmentation_report` as a NUL-terminated string instead of printing
+ * it.
+ */
+char *gc_format_fragmentation_report(void);
+
+/** Prints the number of live objects (the ones reachable from the roots, and the immortal
+ * objects) grouped into power-of-two size classes.
+ */
+void gc_print_size_histogram(void);
+
+/** Returns the output of `gc_print_size_histogram` as a NUL-terminated string instead of printing
+ * it.
+ */
+char *gc_format_size_histogram(void);
+
+/** Returns 1 if calling `gc_alloc(size_in_bytes)` right now would start a GC cycle, and 0
+ * otherwise.
+ */
+int gc_would_trigger_gc(size_t size_in_bytes);
+
+/** Allocates an object of at least `size_in_bytes` bytes in the immortal space.
+ */
+void *gc_alloc_immortal(size_t size_in_bytes);
+
+/** Pushes `count` roots from the array `slots` onto the root stack, in order.
+ */
+void gc_push_roots(void **const *slots, size_t count);
+
+/** Updates each of the `count` object pointers in the array `slots` to the current address of the
+ * object it refers to.
+ */
+void gc_refresh_slots(void **slots, size_t count);
+
+/** Pops `count` roots off the root stack.
+ */
+void gc_pop_roots(size_t count);
+
+/** Returns the total size of the GC-managed objects reachable from `obj`, including `obj` itself.
+ */
+size_t gc_reachable_bytes(void *obj);
+
+/** Counts the GC-managed objects reachable from the roots without collecting or moving anything.
+ */
+void gc_mark_and_count(size_t *obj_count, size_t *byte_count);
+
+/** Estimates how many bytes would survive a GC cycle started right now, without collecting.
+ */
+size_t gc_estimate_live_bytes(void);
+
+/** Returns a 64-bit digest of the live heap, suitable for detecting silent corruption.
+ */
+uint64_t gc_heap_checksum(void);
+
+/** Runs a full GC cycle to completion, finishing the one in progress first, if any.
+ */
+void gc_collect_and_report(size_t *survived, size_t *reclaimed);
+
+/** Enables or disables tracking the GC statistics (enabled by default).
+ */
+void gc_set_stats_enabled(int enabled);
+
+/** Stores a snapshot of the GC statistics in `out`.
+ */
+void gc_get_stats(GcStatsFfi *out);
+
+/** Stores the field-wise difference between two snapshots of the GC statistics in `out`.
+ */
+void gc_stats_delta(const GcStatsFfi *before, const GcStatsFfi *after, GcStatsFfi *out);
+
+/** Returns the number of bytes allocated since the last GC cycle started (or since the start of the
+ * program if there hasn't been one).
+ */
+size_t gc_allocated_since_gc(void);
+
+/** Returns the number of objects allocated since the last GC cycle started (or since the start of
+ * the program if there hasn't been one).
+ */
+size_t gc_total_allocations_since_gc(void);
+
+/** Runs a full GC cycle to completion, finishing the one in progress first, if any.
+ */
+void gc_collect_now(void);
+
+/** Selects when allocation starts a GC cycle: - 0 (the default): once the to-space can't fit the
+ * allocation; `n` is ignored.
+ */
+void gc_set_collection_policy(int policy, size_t n);
+
+/** Enables or disables printing a one-line summary of every finished GC cycle to stderr.
+ */
+void gc_set_cycle_logging(int enabled);
+
+/** Enables or disables checking the whole heap at the end of every GC cycle (disabled by default).
+ */
+void gc_set_verify_after_collect(int enabled);
+
+/** Sets whether popping from an empty root stack or pushing a null root slot aborts the process.
+ */
+void gc_set_strict_roots(int enabled);
+
+/** Starts a new allocation epoch and returns its number.
+ */
+uint64_t gc_epoch_begin(void);
+
+/** Calls `cb` for every object allocated in `epoch` or later, passing `ctx` along.
+ */
+void gc_epoch_objects(uint64_t epoch, void (*cb)(void *obj, void *ctx), void *ctx);
+
+/** Performs a minor collection of the objects allocated at or after `since_offset` bytes from the
+ * start of the to-space.
+ */
+void gc_minor_collect(size_t since_offset);
+
+/** Creates a deep copy of the object graph reachable from `src`.
+ */
+void *gc_copy_object(void *src);
+
+/** Moves `obj` to the immortal space right away and returns its new address.
+ */
+void *gc_force_promote(void *obj);
+
+/** Same as `gc_force_promote`, but also moves every object reachable from `obj` that is still in
+ * the semi-spaces.
+ */
+void *gc_force_promote_graph(void *obj);
+
+/** Serializes the object graph reachable from `root` into the caller-owned buffer `out` of `cap`
+ * bytes.
+ */
+size_t gc_copy_out(void *root, uint8_t *out, size_t cap);
+
+/** Recreates an object graph serialized by `gc_copy_out` from the `len` bytes at `buf`.
+ */
+void *gc_copy_in(const uint8_t *buf, size_t len);
+
+/** Runs a full GC cycle to completion and returns a position-independent snapshot of the surviving
+ * heap.
+ */
+uint8_t *gc_collect_into_snapshot(size_t *out_len);
+
+/** Releases a snapshot of `len` bytes returned by `gc_collect_into_snapshot`.
+ */
+void gc_free_snapshot(uint8_t *buf, size_t len);
+
+/** Replaces the collector with a fresh instance whose semi-spaces are the two caller-provided
+ * buffers of `size` bytes at `from_base` and `to_base`.
+ * Only available with the `testing` feature.
+ */
+void gc_use_fixed_spaces(uint8_t *from_base, uint8_t *to_base, size_t size);
+
+/** Sets the maximum number of roots forwarded per increment of a GC cycle, or makes every cycle
+ * forward all of them as soon as it starts if `count` is 0.
+ */
+void gc_set_root_slice(size_t count);
+
+/** Sets whether a read barrier, once triggered, forwards all object fields of the object being read
+ * rather than just the one field.
+ */
+void gc_set_eager_forward(int enabled);
+
+/** Registers a root holding `obj` that's stored by the GC itself, and returns a handle to it.
+ */
+size_t gc_add_value_root(void *obj);
+
+/** Returns the current value of the root `handle` returned by `gc_add_value_root`.
+ */
+void *gc_update_value_root(size_t handle);
+
+/** Releases the root `handle` returned by `gc_add_value_root`.
+ */
+void gc_remove_value_root(size_t handle);
+
+/** Releases the memory the root stack has retained beyond its current size.
+ */
+void gc_trim_roots(void);
+
+/** Sets the size (in bytes) the semi-spaces grow to if the survivors of a GC cycle occupy more than
+ * half of the to-space.
+ */
+void gc_set_min_heap(size_t bytes);
+
+/** Faults in the free part of the to-space by touching each of its pages once.
+ */
+void gc_prefault(void);
+
+/** Returns the memory region `ptr` points into: 0 if it's not managed by the GC, 1 for the from-
+ * space, 2 for the to-space, or 3 for the immortal space.
+ */
+int gc_space_class(void *ptr);
+
+/** Stores the address ranges of the semi-spaces: `from_start..from_end` for the from-space and
+ * `to_start..to_end` for the to-space.
+ */
+void gc_heap_bounds(size_t *from_start, size_t *from_end, size_t *to_start, size_t *to_end);
+
+/** Renders the GC-managed objects reachable from `root` as a Graphviz digraph.
+ */
+char *gc_dump_object_graph_dot(void *root, size_t *out_len);
+
+/** Releases a string returned by the GC.
+ */
+void gc_free_string(char *s);
+
+/** Reserves capacity for at least `capacity` more roots in the root stack.
+ */
+void gc_reserve_roots(size_t capacity);
+
+/** Registers a callback to run when the GC is shut down with `gc_shutdown`.
+ */
+void gc_at_exit(void (*cb)(void));
+
+/** Tears down the GC, resetting it to the state it was in at startup.
+ */
+void gc_shutdown(void);
+
+/** Chooses what happens on a fatal error, such as running out of memory or detecting heap
+ * corruption.
+ */
+void gc_set_panic_strategy(int strategy, void (*escape)(void));
+
+/** Sets the share of the heap (in percent) that must be left free after a GC cycle.
+ */
+void gc_set_survivor_headroom(uint8_t percent);
+
+/** Sets a callback to notify the host of memory pressure, or removes it if `cb` is null.
+ */
+void gc_set_pressure_callback(uint8_t threshold_percent, void (*cb)(size_t used, size_t total));
+
+/** Sets a callback to notify the host once a GC cycle completes, or removes it if `cb` is null.
+ */
+void gc_set_post_gc_callback(void (*cb)(size_t used, size_t total, size_t survived, size_t cycle));
+
+/** Registers `cb` to be called with `ctx` once `obj` dies, or removes the finalizer of `obj` if
+ * `cb` is null.
+ */
+void gc_register_finalizer(void *obj, void (*cb)(void *ctx), void *ctx);
+
+/** Sets the order in which the finalizers of objects dying together run.
+ */
+void gc_set_finalization_order(int referents_first);
+
+/** Suppresses garbage collection until a matching `gc_unpin_cycle` call, and returns the number of
+ * GC cycles started so far.
+ */
+uint64_t gc_pin_cycle(void);
+
+/** Ends a region started by `gc_pin_cycle`, letting garbage collection resume once every such
+ * region has ended.
+ */
+void gc_unpin_cycle(void);
+
+/** Registers the range `low..high` (typically the mutator's stack) to be scanned for conservative
+ * roots at the start of every GC cycle, or disables the scan if `low` is null.
+ * Only available with the `conservative` feature.
+ */
+void gc_set_stack_bounds(const void *low, const void *high);
+
+/** Sets the function used to render `Raw` fields (such as the code pointer of a closure) when
+ * objects are printed, or restores the default hexadecimal rendering if `formatter` is null.
+ */
+void gc_set_raw_field_formatter(void (*formatter)(void *obj, int field_idx, char *buf, size_t len));
+
+/** Makes field `field_idx` of every object tagged `tag` conditionally traced, gated on field
+ * `key_idx`, or removes the rule for `tag` if `key_idx` is negative.
+ */
+void gc_set_conditional_field(int tag, int field_idx, int key_idx);
+
+/** Selects how the mutator's accesses are reconciled with an ongoing GC cycle: 0 for Baker-style
+ * forwarding (the default), 1 for Brooks-style forwarding, 2 for Baker-style forwarding with a
+ * snapshot-at-the-beginning write barrier.
+ */
+void gc_set_barrier_mode(int mode);
+
+/** Selects the order in which the collector evacuates objects: 0 for semi-depth-first order (the
+ * default), 1 for breadth-first order.
+ */
+void gc_set_scan_order(int order);
+
+/** Returns a description of the most recent recoverable failure, or null if there has been none.
+ */
+const char *gc_last_error(void);
+
 #endif
@@ -37,8 +37,8 @@ stella_object* alloc_stella_object(enum TAG tag, int fields_count) {
 
 stella_object *nat_to_stella_object(int n) {
   stella_object *result, *x;
-  gc_push_root((void*)&result);    // it is sufficient to push only result
   result = &the_ZERO;
+  gc_push_root((void*)&result);    // it is sufficient to push only result
   for (int i = n; i > 0; i--) {
     x = alloc_stella_object(TAG_SUCC, 1);
     STELLA_OBJECT_INIT_FIELD(x, 0, result);